@@ -1,15 +1,37 @@
+use aws_sdk_secretsmanager::error::SdkError;
+use aws_sdk_secretsmanager::operation::get_secret_value::GetSecretValueError;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
 pub enum GatewayError {
     #[error("Invalid request: {0}")]
     InvalidRequest(String),
+    #[error("Unauthorized: {0}")]
+    Unauthorized(String),
+    #[error("Forbidden: {0}")]
+    Forbidden(String),
+    #[error("Unknown store: {0}")]
+    UnknownStore(String),
+    /// An off-session payment was declined until the customer authenticates it on-session.
+    #[error("Authentication required: {message}")]
+    AuthenticationRequired {
+        message: String,
+        payment_intent_id: Option<String>,
+    },
     #[error("Stripe API error: {0}")]
     StripeError(#[from] reqwest::Error),
     #[error("Secrets Manager error: {0}")]
-    SecretsManagerError(#[from] aws_sdk_secretsmanager::error::SdkError<aws_sdk_secretsmanager::operation::get_secret_value::GetSecretValueError>),
+    SecretsManagerError(Box<SdkError<GetSecretValueError>>),
+    #[error("Storage error: {0}")]
+    StorageError(String),
     #[error("Serialization error: {0}")]
     SerializationError(#[from] serde_json::Error),
     #[error("Unexpected error: {0}")]
     Unexpected(String),
-}
\ No newline at end of file
+}
+
+impl From<SdkError<GetSecretValueError>> for GatewayError {
+    fn from(error: SdkError<GetSecretValueError>) -> Self {
+        GatewayError::SecretsManagerError(Box::new(error))
+    }
+}
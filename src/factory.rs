@@ -1,9 +1,22 @@
+use std::collections::HashMap;
+use std::sync::Arc;
 use async_trait::async_trait;
 use crate::errors::GatewayError;
-use crate::models::PaymentRequest;
+use crate::ledger::Stores;
+use crate::merchant_webhooks::MerchantNotifier;
+use crate::metrics;
+use crate::models::{DisputeResponse, PaymentRequest, TransactionRecord};
+use crate::publisher::EventRouter;
+use crate::services::StoreSecret;
+use crate::stripe::StripeClient;
 use crate::processors::{
-    ChargeProcessor, PaymentLinkProcessor, RefundProcessor, StatusProcessor, WebhookProcessor,
-    StripeChargeProcessor, StripePaymentLinkProcessor, StripeRefundProcessor, StripeStatusProcessor, StripeWebhookProcessor
+    ChargeProcessor, AuthorizationProcessor, PaymentIntentProcessor, PaymentLinkProcessor, RefundProcessor, StatusProcessor, WebhookProcessor, AccountProcessor,
+    HistoryProcessor, DeliveryProcessor, ListProcessor, DisputeProcessor, SetupIntentProcessor, CatalogProcessor, InvoiceProcessor, BillingPortalProcessor, DiscountProcessor, BalanceProcessor, StripeChargeProcessor, StripePaymentLinkProcessor, StripeRefundProcessor,
+    StripeStatusProcessor, StripeWebhookProcessor, StripeAccountProcessor, LedgerHistoryProcessor,
+    MerchantWebhookDeliveryProcessor, StripeListProcessor, StripeAuthorizationProcessor,
+    StripeDisputeProcessor, StripePaymentIntentProcessor, StripeSetupIntentProcessor, StripeCatalogProcessor,
+    StripeInvoiceProcessor, StripeBillingPortalProcessor,
+    StripeDiscountProcessor, StripeBalanceProcessor
 };
 
 #[async_trait]
@@ -12,7 +25,11 @@ pub trait PaymentProcessor {
 }
 
 pub struct PaymentProcessorFactory {
-    api_key: String,
+    secret: StoreSecret,
+    stores: Arc<Stores>,
+    router: Arc<EventRouter>,
+    /// Shared across invocations for outbound merchant webhooks.
+    http_client: reqwest::Client,
 }
 
 #[async_trait]
@@ -20,28 +37,300 @@ impl PaymentProcessor for PaymentProcessorFactory {
     async fn process_payment(&self, request: &PaymentRequest) -> Result<serde_json::Value, GatewayError> {
         match request.request_type.to_uppercase().as_str() {
             "CHARGE" => {
-                let processor = StripeChargeProcessor::new(self.api_key.clone());
+                let processor = StripeChargeProcessor::new(self.client(request), self.stores.redemptions.clone());
                 let response = processor.process_charge(request).await?;
+                // An off-session charge awaiting authentication has no settled charge yet,
+                // so it is tracked under its payment intent until the customer completes it.
+                let requires_action = response.payment_status.as_deref() == Some("requires_action");
+                let tracked = match (&response.charge_id, &response.payment_intent_id) {
+                    (_, Some(payment_intent_id)) if requires_action => {
+                        let mut record = TransactionRecord::new(request, "payment_intent", payment_intent_id.clone());
+                        record.related_object_id = response.charge_id.clone();
+                        Some(record)
+                    }
+                    (Some(charge_id), _) => Some(TransactionRecord::new(request, "charge", charge_id.clone())),
+                    _ => None,
+                };
+                if let Some(mut record) = tracked {
+                    record.status = response.payment_status.clone();
+                    record.amount = response.amount;
+                    record.currency = response.currency.clone();
+                    self.record(&record).await;
+                    self.notify(&record).await;
+                }
+                Ok(serde_json::to_value(response)?)
+            }
+            "AUTHORIZE" | "CAPTURE" | "VOID" | "CANCEL" => {
+                let processor = StripeAuthorizationProcessor::new(self.client(request));
+                let response = match request.request_type.to_uppercase().as_str() {
+                    "AUTHORIZE" => processor.authorize(request).await?,
+                    "CAPTURE" => processor.capture(request).await?,
+                    _ => processor.void(request).await?,
+                };
+                if let Some(payment_intent_id) = &response.payment_intent_id {
+                    let mut record = TransactionRecord::new(request, "payment_intent", payment_intent_id.clone());
+                    record.status = response.payment_status.clone();
+                    record.amount = response.amount;
+                    record.currency = response.currency.clone();
+                    record.related_object_id = response.charge_id.clone();
+                    self.record(&record).await;
+                    self.notify(&record).await;
+                }
+                Ok(serde_json::to_value(response)?)
+            }
+            "SETUP_INTENT" => {
+                let processor = StripeSetupIntentProcessor::new(self.client(request));
+                let response = processor.create_setup_intent(request).await?;
+                if let Some(setup_intent_id) = &response.setup_intent_id {
+                    let mut record = TransactionRecord::new(request, "setup_intent", setup_intent_id.clone());
+                    record.status = response.setup_status.clone();
+                    self.record(&record).await;
+                }
+                Ok(serde_json::to_value(response)?)
+            }
+            "PAYMENT_INTENT" => {
+                let processor = StripePaymentIntentProcessor::new(self.client(request));
+                let response = processor.create_payment_intent(request).await?;
+                if let Some(payment_intent_id) = &response.payment_intent_id {
+                    let mut record = TransactionRecord::new(request, "payment_intent", payment_intent_id.clone());
+                    record.status = response.payment_status.clone();
+                    record.amount = response.amount;
+                    record.currency = response.currency.clone();
+                    self.record(&record).await;
+                }
                 Ok(serde_json::to_value(response)?)
             }
             "PAYMENT_LINK" => {
-                let processor = StripePaymentLinkProcessor::new(self.api_key.clone());
+                let processor = StripePaymentLinkProcessor::new(self.client(request));
                 let response = processor.process_payment_link(request).await?;
+                if let Some(session_id) = &response.session_id {
+                    let mut record = TransactionRecord::new(request, "checkout_session", session_id.clone());
+                    record.status = response.session_status.clone();
+                    // A catalog price or a discount sets the total, not the request's amount.
+                    record.amount = response.amount_total;
+                    record.currency = response.currency.clone();
+                    self.record(&record).await;
+                    self.notify(&record).await;
+                }
+                Ok(serde_json::to_value(response)?)
+            }
+            "EXPIRE_SESSION" => {
+                let processor = StripePaymentLinkProcessor::new(self.client(request));
+                let response = processor.expire_session(request).await?;
+                if let Some(session_id) = &response.session_id {
+                    let mut record = match self.stores.transactions.get(&request.store_id, session_id).await {
+                        Ok(Some(existing)) => existing,
+                        _ => TransactionRecord::new(request, "checkout_session", session_id.clone()),
+                    };
+                    record.status = response.session_status.clone();
+                    self.record(&record).await;
+                    self.notify(&record).await;
+                }
+                Ok(serde_json::to_value(response)?)
+            }
+            "GET_SESSION_LINE_ITEMS" => {
+                let processor = StripeStatusProcessor::new(self.client(request));
+                let response = processor.session_line_items(request).await?;
                 Ok(serde_json::to_value(response)?)
             }
             "REFUND" => {
-                let processor = StripeRefundProcessor::new(self.api_key.clone());
+                let processor = StripeRefundProcessor::new(self.client(request));
                 let response = processor.process_refund(request).await?;
+                if let Some(refund_id) = &response.refund_id {
+                    let mut record = TransactionRecord::new(request, "refund", refund_id.clone());
+                    record.status = response.refund_status.clone();
+                    record.amount = response.amount;
+                    record.currency = response.currency.clone();
+                    record.related_object_id = request.charge_id.clone();
+                    self.record(&record).await;
+                    self.notify(&record).await;
+                }
                 Ok(serde_json::to_value(response)?)
             }
             "STATUS" => {
-                let processor = StripeStatusProcessor::new(self.api_key.clone());
+                let processor = StripeStatusProcessor::new(self.client(request));
                 let response = processor.process_status(request).await?;
                 Ok(serde_json::to_value(response)?)
             }
             "WEBHOOK" => {
-                let processor = StripeWebhookProcessor::new(self.api_key.clone());
+                let processor = StripeWebhookProcessor::new(
+                    self.client(request),
+                    self.stores.webhook_events.clone(),
+                    self.router.clone(),
+                );
                 let response = processor.process_webhook(request).await?;
+                if response.duplicate {
+                    return Ok(serde_json::to_value(response)?);
+                }
+                if let (Some(event_id), Some(event)) = (&response.event_id, &request.webhook_event) {
+                    let object = event.get("data").map(|data| &data["object"]);
+                    let mut record = TransactionRecord::new(request, "webhook_event", event_id.clone());
+                    record.event_type = event.get("type").and_then(|v| v.as_str()).map(String::from);
+                    record.related_object_id = object.and_then(|o| o["id"].as_str()).map(String::from);
+                    record.status = object.and_then(|o| o["status"].as_str()).map(String::from);
+                    record.amount = object.and_then(|o| o["amount"].as_i64().or_else(|| o["amount_due"].as_i64()));
+                    record.currency = object.and_then(|o| o["currency"].as_str()).map(String::from);
+                    record.metadata = object
+                        .and_then(|o| serde_json::from_value(o["metadata"].clone()).ok())
+                        .filter(|metadata: &HashMap<String, String>| !metadata.is_empty());
+                    self.record(&record).await;
+                    if !response.stale {
+                        self.track_object(request, &record, object).await;
+                        self.confirm_off_session_charge(request, &record, object).await;
+                    }
+                }
+                Ok(serde_json::to_value(response)?)
+            }
+            "LIST_CHARGES" | "LIST_PAYMENT_INTENTS" | "LIST_REFUNDS" | "LIST_CHECKOUT_SESSIONS" | "LIST_CUSTOMERS" => {
+                let processor = StripeListProcessor::new(self.client(request));
+                let response = processor.process_list(request).await?;
+                Ok(serde_json::to_value(response)?)
+            }
+            "CREATE_PRODUCT" | "UPDATE_PRODUCT" | "ARCHIVE_PRODUCT" | "LIST_PRODUCTS" => {
+                let processor = StripeCatalogProcessor::new(self.client(request));
+                let response = match request.request_type.to_uppercase().as_str() {
+                    "CREATE_PRODUCT" => processor.create_product(request).await?,
+                    "UPDATE_PRODUCT" => processor.update_product(request).await?,
+                    "ARCHIVE_PRODUCT" => processor.archive_product(request).await?,
+                    _ => processor.list_products(request).await?,
+                };
+                Ok(serde_json::to_value(response)?)
+            }
+            "CREATE_PRICE" | "UPDATE_PRICE" | "ARCHIVE_PRICE" | "LIST_PRICES" => {
+                let processor = StripeCatalogProcessor::new(self.client(request));
+                let response = match request.request_type.to_uppercase().as_str() {
+                    "CREATE_PRICE" => processor.create_price(request).await?,
+                    "UPDATE_PRICE" => processor.update_price(request).await?,
+                    "ARCHIVE_PRICE" => processor.archive_price(request).await?,
+                    _ => processor.list_prices(request).await?,
+                };
+                Ok(serde_json::to_value(response)?)
+            }
+            "CREATE_INVOICE" | "GET_INVOICE" | "FINALIZE_INVOICE" | "SEND_INVOICE" | "VOID_INVOICE"
+            | "MARK_INVOICE_UNCOLLECTIBLE" | "PAY_INVOICE" => {
+                let processor = StripeInvoiceProcessor::new(self.client(request));
+                let response = match request.request_type.to_uppercase().as_str() {
+                    "CREATE_INVOICE" => processor.create_invoice(request).await?,
+                    "GET_INVOICE" => processor.retrieve_invoice(request).await?,
+                    "FINALIZE_INVOICE" => processor.finalize_invoice(request).await?,
+                    "SEND_INVOICE" => processor.send_invoice(request).await?,
+                    "VOID_INVOICE" => processor.void_invoice(request).await?,
+                    "MARK_INVOICE_UNCOLLECTIBLE" => processor.mark_uncollectible(request).await?,
+                    _ => processor.pay_invoice(request).await?,
+                };
+                if let Some(invoice_id) = &response.invoice.invoice_id {
+                    let mut record = match self.stores.transactions.get(&request.store_id, invoice_id).await {
+                        Ok(Some(existing)) => existing,
+                        _ => TransactionRecord::new(request, "invoice", invoice_id.clone()),
+                    };
+                    if record.status != response.invoice.invoice_status {
+                        record.status = response.invoice.invoice_status.clone();
+                        record.amount = response.invoice.amount_due;
+                        record.currency = response.invoice.currency.clone();
+                        self.record(&record).await;
+                        self.notify(&record).await;
+                    }
+                }
+                Ok(serde_json::to_value(response)?)
+            }
+            "BILLING_PORTAL_SESSION" => {
+                let processor = StripeBillingPortalProcessor::new(
+                    self.client(request),
+                    self.secret.billing_portal_configuration.clone(),
+                );
+                let response = processor.create_portal_session(request).await?;
+                Ok(serde_json::to_value(response)?)
+            }
+            "CREATE_COUPON" | "LIST_COUPONS" | "DEACTIVATE_COUPON" => {
+                let processor = StripeDiscountProcessor::new(self.client(request));
+                let response = match request.request_type.to_uppercase().as_str() {
+                    "CREATE_COUPON" => processor.create_coupon(request).await?,
+                    "LIST_COUPONS" => processor.list_coupons(request).await?,
+                    _ => processor.deactivate_coupon(request).await?,
+                };
+                Ok(serde_json::to_value(response)?)
+            }
+            "CREATE_PROMOTION_CODE" | "LIST_PROMOTION_CODES" | "DEACTIVATE_PROMOTION_CODE" => {
+                let processor = StripeDiscountProcessor::new(self.client(request));
+                let response = match request.request_type.to_uppercase().as_str() {
+                    "CREATE_PROMOTION_CODE" => processor.create_promotion_code(request).await?,
+                    "LIST_PROMOTION_CODES" => processor.list_promotion_codes(request).await?,
+                    _ => processor.deactivate_promotion_code(request).await?,
+                };
+                Ok(serde_json::to_value(response)?)
+            }
+            "GET_BALANCE" | "LIST_BALANCE_TRANSACTIONS" | "LIST_PAYOUTS" | "GET_PAYOUT" => {
+                // `accountId` reports on a connected account instead of the store's own.
+                let client = self.client(request).with_stripe_account(request.account_id.clone());
+                let processor = StripeBalanceProcessor::new(client);
+                let response = match request.request_type.to_uppercase().as_str() {
+                    "GET_BALANCE" => serde_json::to_value(processor.retrieve_balance(request).await?)?,
+                    "LIST_BALANCE_TRANSACTIONS" => serde_json::to_value(processor.list_balance_transactions(request).await?)?,
+                    "LIST_PAYOUTS" => serde_json::to_value(processor.list_payouts(request).await?)?,
+                    _ => serde_json::to_value(processor.retrieve_payout(request).await?)?,
+                };
+                Ok(response)
+            }
+            "LIST_DISPUTES" => {
+                let processor = StripeDisputeProcessor::new(self.client(request));
+                let response = processor.list_disputes(request).await?;
+                Ok(serde_json::to_value(response)?)
+            }
+            "GET_DISPUTE" => {
+                let processor = StripeDisputeProcessor::new(self.client(request));
+                let response = processor.retrieve_dispute(request).await?;
+                Ok(serde_json::to_value(response)?)
+            }
+            "SUBMIT_DISPUTE_EVIDENCE" => {
+                let processor = StripeDisputeProcessor::new(self.client(request));
+                let response = processor.submit_evidence(request).await?;
+                self.record_disputes(request, &response).await;
+                Ok(serde_json::to_value(response)?)
+            }
+            "CLOSE_DISPUTE" => {
+                let processor = StripeDisputeProcessor::new(self.client(request));
+                let response = processor.close_dispute(request).await?;
+                self.record_disputes(request, &response).await;
+                Ok(serde_json::to_value(response)?)
+            }
+            "CREATE_ACCOUNT" => {
+                let processor = StripeAccountProcessor::new(self.client(request));
+                let response = processor.create_account(request).await?;
+                Ok(serde_json::to_value(response)?)
+            }
+            "ACCOUNT_LINK" => {
+                let processor = StripeAccountProcessor::new(self.client(request));
+                let response = processor.create_account_link(request).await?;
+                Ok(serde_json::to_value(response)?)
+            }
+            "ACCOUNT_STATUS" => {
+                let processor = StripeAccountProcessor::new(self.client(request));
+                let response = processor.retrieve_account(request).await?;
+                Ok(serde_json::to_value(response)?)
+            }
+            "LOGIN_LINK" => {
+                let processor = StripeAccountProcessor::new(self.client(request));
+                let response = processor.create_login_link(request).await?;
+                Ok(serde_json::to_value(response)?)
+            }
+            "WEBHOOK_DELIVERIES" => {
+                let processor = self.delivery_processor();
+                let response = processor.list_deliveries(request).await?;
+                Ok(serde_json::to_value(response)?)
+            }
+            "REPLAY_WEBHOOK_DELIVERY" => {
+                let processor = self.delivery_processor();
+                let response = processor.replay_delivery(request).await?;
+                Ok(serde_json::to_value(response)?)
+            }
+            "RETRY_WEBHOOK_DELIVERIES" => {
+                let processor = self.delivery_processor();
+                let response = processor.retry_deliveries(request).await?;
+                Ok(serde_json::to_value(response)?)
+            }
+            "TRANSACTION_HISTORY" => {
+                let processor = LedgerHistoryProcessor::new(self.stores.transactions.clone());
+                let response = processor.process_history(request).await?;
                 Ok(serde_json::to_value(response)?)
             }
             _ => Err(GatewayError::InvalidRequest(format!("Invalid request type: {}", request.request_type))),
@@ -50,7 +339,122 @@ impl PaymentProcessor for PaymentProcessorFactory {
 }
 
 impl PaymentProcessorFactory {
-    pub fn new(api_key: String) -> Self {
-        PaymentProcessorFactory { api_key }
+    pub fn new(secret: StoreSecret, stores: Arc<Stores>, router: Arc<EventRouter>, http_client: reqwest::Client) -> Self {
+        PaymentProcessorFactory { secret, stores, router, http_client }
+    }
+
+    /// The Stripe operation has already happened by the time it is recorded, so a ledger
+    /// failure is logged and counted rather than failing the request.
+    async fn record(&self, record: &TransactionRecord) {
+        if let Err(e) = self.stores.transactions.record(record).await {
+            tracing::error!(object_id = %record.object_id, "Failed to record transaction: {}", e);
+            metrics::count("LedgerWriteErrors", &[]);
+        }
+    }
+
+    /// Like the ledger, merchant notification never fails the request; undelivered
+    /// webhooks stay in the delivery log to be retried.
+    async fn notify(&self, record: &TransactionRecord) {
+        if let Err(e) = self.notifier().notify(record).await {
+            tracing::error!(object_id = %record.object_id, "Failed to notify merchant: {}", e);
+            metrics::count("MerchantWebhookErrors", &[]);
+        }
+    }
+
+    /// Carries a webhook's status onto the ledger record of the charge, refund, dispute or
+    /// checkout session it is about, notifying the merchant when that status changed.
+    async fn track_object(&self, request: &PaymentRequest, event_record: &TransactionRecord, object: Option<&serde_json::Value>) {
+        let (Some(object), Some(object_id), Some(status)) = (object, &event_record.related_object_id, &event_record.status) else {
+            return;
+        };
+        let object_type = match object["object"].as_str() {
+            Some("charge") => "charge",
+            Some("refund") => "refund",
+            Some("checkout.session") => "checkout_session",
+            Some("dispute") => "dispute",
+            Some("invoice") => "invoice",
+            _ => return,
+        };
+
+        let mut record = match self.stores.transactions.get(&request.store_id, object_id).await {
+            Ok(Some(existing)) if existing.status.as_ref() == Some(status) => return,
+            Ok(Some(existing)) => existing,
+            Ok(None) => {
+                let mut record = TransactionRecord::new(request, object_type, object_id.clone());
+                record.amount = event_record.amount;
+                record.currency = event_record.currency.clone();
+                record.metadata = event_record.metadata.clone();
+                record
+            }
+            Err(e) => {
+                tracing::error!(object_id = %object_id, "Failed to read transaction: {}", e);
+                return;
+            }
+        };
+        record.status = Some(status.clone());
+        record.event_type = event_record.event_type.clone();
+        self.record(&record).await;
+        self.notify(&record).await;
+    }
+
+    /// An off-session CHARGE that needed authentication is tracked under its payment intent,
+    /// with its discount's redemptions given back. Once the customer confirms the payment,
+    /// they are counted again and the record moves on.
+    async fn confirm_off_session_charge(&self, request: &PaymentRequest, event_record: &TransactionRecord, object: Option<&serde_json::Value>) {
+        let (Some(object), Some(payment_intent_id)) = (object, &event_record.related_object_id) else {
+            return;
+        };
+        if event_record.event_type.as_deref() != Some("payment_intent.succeeded") {
+            return;
+        }
+        let mut record = match self.stores.transactions.get(&request.store_id, payment_intent_id).await {
+            Ok(Some(existing)) if existing.request_type == "CHARGE" && existing.status.as_deref() == Some("requires_action") => existing,
+            Ok(_) => return,
+            Err(e) => {
+                tracing::error!(object_id = %payment_intent_id, "Failed to read transaction: {}", e);
+                return;
+            }
+        };
+        let processor = StripeChargeProcessor::new(self.client(request), self.stores.redemptions.clone());
+        if let Err(e) = processor.confirm_redemptions(&request.store_id, object).await {
+            tracing::error!(object_id = %payment_intent_id, "Failed to count discount redemptions: {}", e);
+            metrics::count("LedgerWriteErrors", &[]);
+        }
+        record.status = event_record.status.clone();
+        record.event_type = event_record.event_type.clone();
+        record.related_object_id = object["latest_charge"].as_str().map(String::from).or(record.related_object_id);
+        self.record(&record).await;
+        self.notify(&record).await;
     }
-}
\ No newline at end of file
+
+    async fn record_disputes(&self, request: &PaymentRequest, response: &DisputeResponse) {
+        for dispute in &response.disputes {
+            if let Some(dispute_id) = &dispute.dispute_id {
+                let mut record = TransactionRecord::new(request, "dispute", dispute_id.clone());
+                record.status = dispute.dispute_status.clone();
+                record.amount = dispute.amount;
+                record.currency = dispute.currency.clone();
+                record.related_object_id = dispute.charge_id.clone();
+                self.record(&record).await;
+                self.notify(&record).await;
+            }
+        }
+    }
+
+    fn notifier(&self) -> MerchantNotifier {
+        MerchantNotifier::new(
+            self.secret.merchant_webhooks.clone(),
+            self.stores.deliveries.clone(),
+            self.http_client.clone(),
+        )
+    }
+
+    fn delivery_processor(&self) -> MerchantWebhookDeliveryProcessor {
+        MerchantWebhookDeliveryProcessor::new(self.notifier(), self.stores.deliveries.clone())
+    }
+
+    fn client(&self, request: &PaymentRequest) -> StripeClient {
+        StripeClient::new(self.secret.api_key.clone())
+            .with_idempotency_key(request.idempotency_key.clone())
+    }
+}
@@ -0,0 +1,368 @@
+use std::collections::HashMap;
+use async_trait::async_trait;
+use aws_sdk_dynamodb::error::{DisplayErrorContext, SdkError};
+use aws_sdk_dynamodb::operation::put_item::PutItemError;
+use aws_sdk_dynamodb::operation::update_item::UpdateItemError;
+use aws_sdk_dynamodb::types::AttributeValue;
+use aws_sdk_dynamodb::Client as DynamoDbClient;
+use crate::errors::GatewayError;
+use crate::models::{TransactionRecord, WebhookDelivery};
+use super::{now_secs, status_rank, DeliveryStore, ObjectState, RedemptionStore, TransactionStore, WebhookEventStore, DELIVERY_PENDING};
+
+type Item = HashMap<String, AttributeValue>;
+
+/// Ledger table keyed by `storeId` (partition) and `objectId` (sort). Listing uses the
+/// optional `LEDGER_TIME_INDEX` GSI (`storeId` + numeric `recordedAt`) when configured.
+///
+/// Webhook state and discount redemption counts live in a second table keyed by a string
+/// `pk` alone, with TTL enabled on `expiresAt` so expired event claims are cleaned up by DynamoDB.
+///
+/// Merchant webhook deliveries are kept in an optional third table keyed by `storeId`
+/// (partition) and `deliveryId` (sort).
+pub struct DynamoDbTransactionStore {
+    client: DynamoDbClient,
+    table: String,
+    time_index: Option<String>,
+    state_table: String,
+    delivery_table: Option<String>,
+}
+
+impl DynamoDbTransactionStore {
+    pub async fn new(
+        table: String,
+        time_index: Option<String>,
+        state_table: String,
+        delivery_table: Option<String>,
+    ) -> Self {
+        let config = aws_config::load_defaults(aws_config::BehaviorVersion::latest()).await;
+        DynamoDbTransactionStore {
+            client: DynamoDbClient::new(&config),
+            table,
+            time_index,
+            state_table,
+            delivery_table,
+        }
+    }
+
+    fn delivery_table(&self) -> Result<&str, GatewayError> {
+        self.delivery_table.as_deref()
+            .ok_or_else(|| GatewayError::StorageError("WEBHOOK_DELIVERY_TABLE is not configured".to_string()))
+    }
+
+    /// Reads every delivery of the store matching `filter`; the table has no index to order by.
+    async fn query_deliveries(
+        &self,
+        store_id: &str,
+        filter: Option<(&str, Item)>,
+    ) -> Result<Vec<WebhookDelivery>, GatewayError> {
+        let mut query = self.client.query()
+            .table_name(self.delivery_table()?)
+            .key_condition_expression("storeId = :storeId")
+            .expression_attribute_values(":storeId", AttributeValue::S(store_id.to_string()));
+        if let Some((expression, values)) = filter {
+            query = query
+                .filter_expression(expression)
+                .expression_attribute_names("#status", "status");
+            for (name, value) in values {
+                query = query.expression_attribute_values(name, value);
+            }
+        }
+
+        let mut pages = query.into_paginator().items().send();
+        let mut deliveries = Vec::new();
+        while let Some(item) = pages.next().await {
+            let item = item.map_err(|e| GatewayError::StorageError(DisplayErrorContext(&e).to_string()))?;
+            deliveries.push(delivery_from_item(&item)?);
+        }
+        Ok(deliveries)
+    }
+}
+
+#[async_trait]
+impl TransactionStore for DynamoDbTransactionStore {
+    async fn record(&self, record: &TransactionRecord) -> Result<(), GatewayError> {
+        self.client.put_item()
+            .table_name(&self.table)
+            .set_item(Some(to_item(record)?))
+            .send()
+            .await
+            .map_err(|e| GatewayError::StorageError(DisplayErrorContext(&e).to_string()))?;
+        Ok(())
+    }
+
+    async fn get(&self, store_id: &str, object_id: &str) -> Result<Option<TransactionRecord>, GatewayError> {
+        let output = self.client.get_item()
+            .table_name(&self.table)
+            .key("storeId", AttributeValue::S(store_id.to_string()))
+            .key("objectId", AttributeValue::S(object_id.to_string()))
+            .send()
+            .await
+            .map_err(|e| GatewayError::StorageError(DisplayErrorContext(&e).to_string()))?;
+        output.item.as_ref().map(from_item).transpose()
+    }
+
+    async fn list(&self, store_id: &str, limit: usize) -> Result<Vec<TransactionRecord>, GatewayError> {
+        let query = self.client.query()
+            .table_name(&self.table)
+            .key_condition_expression("storeId = :storeId")
+            .expression_attribute_values(":storeId", AttributeValue::S(store_id.to_string()));
+
+        if let Some(index) = &self.time_index {
+            let output = query
+                .index_name(index)
+                .scan_index_forward(false)
+                .limit(limit as i32)
+                .send()
+                .await
+                .map_err(|e| GatewayError::StorageError(DisplayErrorContext(&e).to_string()))?;
+            return output.items().iter().map(from_item).collect();
+        }
+
+        // Without the time index every record of the store has to be read to order them.
+        let mut pages = query.into_paginator().items().send();
+        let mut records = Vec::new();
+        while let Some(item) = pages.next().await {
+            let item = item.map_err(|e| GatewayError::StorageError(DisplayErrorContext(&e).to_string()))?;
+            records.push(from_item(&item)?);
+        }
+        records.sort_by_key(|record| std::cmp::Reverse(record.recorded_at));
+        records.truncate(limit);
+        Ok(records)
+    }
+}
+
+#[async_trait]
+impl WebhookEventStore for DynamoDbTransactionStore {
+    async fn claim_event(&self, store_id: &str, event_id: &str, ttl_secs: i64) -> Result<bool, GatewayError> {
+        let now = now_secs();
+        let result = self.client.put_item()
+            .table_name(&self.state_table)
+            .item("pk", AttributeValue::S(format!("event#{}#{}", store_id, event_id)))
+            .item("expiresAt", AttributeValue::N((now + ttl_secs).to_string()))
+            // TTL deletion is lazy, so an expired claim may still be present.
+            .condition_expression("attribute_not_exists(pk) OR expiresAt <= :now")
+            .expression_attribute_values(":now", AttributeValue::N(now.to_string()))
+            .send()
+            .await;
+        conditional_put(result)
+    }
+
+    async fn release_event(&self, store_id: &str, event_id: &str) -> Result<(), GatewayError> {
+        self.client.delete_item()
+            .table_name(&self.state_table)
+            .key("pk", AttributeValue::S(format!("event#{}#{}", store_id, event_id)))
+            .send()
+            .await
+            .map_err(|e| GatewayError::StorageError(DisplayErrorContext(&e).to_string()))?;
+        Ok(())
+    }
+
+    async fn advance_object_state(&self, store_id: &str, state: &ObjectState) -> Result<bool, GatewayError> {
+        let result = self.client.put_item()
+            .table_name(&self.state_table)
+            .item("pk", AttributeValue::S(format!("object#{}#{}", store_id, state.object_id)))
+            .item("status", AttributeValue::S(state.status.clone()))
+            .item("eventId", AttributeValue::S(state.event_id.clone()))
+            .item("eventCreated", AttributeValue::N(state.event_created.to_string()))
+            .item("statusRank", AttributeValue::N(status_rank(&state.status).to_string()))
+            .condition_expression(
+                "attribute_not_exists(pk) OR eventCreated < :created OR eventId = :eventId \
+                 OR (eventCreated = :created AND statusRank < :rank)",
+            )
+            .expression_attribute_values(":created", AttributeValue::N(state.event_created.to_string()))
+            .expression_attribute_values(":eventId", AttributeValue::S(state.event_id.clone()))
+            .expression_attribute_values(":rank", AttributeValue::N(status_rank(&state.status).to_string()))
+            .send()
+            .await;
+        conditional_put(result)
+    }
+}
+
+#[async_trait]
+impl DeliveryStore for DynamoDbTransactionStore {
+    async fn save_delivery(&self, delivery: &WebhookDelivery) -> Result<(), GatewayError> {
+        self.client.put_item()
+            .table_name(self.delivery_table()?)
+            .set_item(Some(delivery_to_item(delivery)?))
+            .send()
+            .await
+            .map_err(|e| GatewayError::StorageError(DisplayErrorContext(&e).to_string()))?;
+        Ok(())
+    }
+
+    async fn get_delivery(&self, store_id: &str, delivery_id: &str) -> Result<Option<WebhookDelivery>, GatewayError> {
+        let output = self.client.get_item()
+            .table_name(self.delivery_table()?)
+            .key("storeId", AttributeValue::S(store_id.to_string()))
+            .key("deliveryId", AttributeValue::S(delivery_id.to_string()))
+            .send()
+            .await
+            .map_err(|e| GatewayError::StorageError(DisplayErrorContext(&e).to_string()))?;
+        output.item.as_ref().map(delivery_from_item).transpose()
+    }
+
+    async fn list_deliveries(&self, store_id: &str, limit: usize) -> Result<Vec<WebhookDelivery>, GatewayError> {
+        let mut deliveries = self.query_deliveries(store_id, None).await?;
+        deliveries.sort_by_key(|delivery| std::cmp::Reverse(delivery.created_at));
+        deliveries.truncate(limit);
+        Ok(deliveries)
+    }
+
+    async fn due_deliveries(&self, store_id: &str, now: i64, limit: usize) -> Result<Vec<WebhookDelivery>, GatewayError> {
+        let values = Item::from([
+            (":pending".to_string(), AttributeValue::S(DELIVERY_PENDING.to_string())),
+            (":now".to_string(), AttributeValue::N(now.to_string())),
+        ]);
+        let filter = "#status = :pending AND nextAttemptAt <= :now";
+        let mut deliveries = self.query_deliveries(store_id, Some((filter, values))).await?;
+        deliveries.sort_by_key(|delivery| delivery.next_attempt_at);
+        deliveries.truncate(limit);
+        Ok(deliveries)
+    }
+}
+
+#[async_trait]
+impl RedemptionStore for DynamoDbTransactionStore {
+    async fn claim_redemption(&self, store_id: &str, discount_id: &str, limit: Option<i64>) -> Result<bool, GatewayError> {
+        let mut update = self.client.update_item()
+            .table_name(&self.state_table)
+            .key("pk", AttributeValue::S(format!("redemption#{}#{}", store_id, discount_id)))
+            .update_expression("ADD redemptions :one")
+            .expression_attribute_values(":one", AttributeValue::N("1".to_string()));
+        if let Some(limit) = limit {
+            if limit <= 0 {
+                return Ok(false);
+            }
+            update = update
+                .condition_expression("attribute_not_exists(redemptions) OR redemptions < :limit")
+                .expression_attribute_values(":limit", AttributeValue::N(limit.to_string()));
+        }
+        conditional_update(update.send().await)
+    }
+
+    async fn release_redemption(&self, store_id: &str, discount_id: &str) -> Result<(), GatewayError> {
+        let result = self.client.update_item()
+            .table_name(&self.state_table)
+            .key("pk", AttributeValue::S(format!("redemption#{}#{}", store_id, discount_id)))
+            .update_expression("ADD redemptions :minusOne")
+            .condition_expression("redemptions > :zero")
+            .expression_attribute_values(":minusOne", AttributeValue::N("-1".to_string()))
+            .expression_attribute_values(":zero", AttributeValue::N("0".to_string()))
+            .send()
+            .await;
+        conditional_update(result).map(|_| ())
+    }
+}
+
+/// Maps a failed write condition to `false` instead of an error.
+fn conditional_put<T>(result: Result<T, SdkError<PutItemError>>) -> Result<bool, GatewayError> {
+    match result {
+        Ok(_) => Ok(true),
+        Err(e) if e.as_service_error().is_some_and(|e| e.is_conditional_check_failed_exception()) => Ok(false),
+        Err(e) => Err(GatewayError::StorageError(DisplayErrorContext(&e).to_string())),
+    }
+}
+
+fn conditional_update<T>(result: Result<T, SdkError<UpdateItemError>>) -> Result<bool, GatewayError> {
+    match result {
+        Ok(_) => Ok(true),
+        Err(e) if e.as_service_error().is_some_and(|e| e.is_conditional_check_failed_exception()) => Ok(false),
+        Err(e) => Err(GatewayError::StorageError(DisplayErrorContext(&e).to_string())),
+    }
+}
+
+fn to_item(record: &TransactionRecord) -> Result<Item, GatewayError> {
+    let mut item = Item::new();
+    item.insert("storeId".to_string(), AttributeValue::S(record.store_id.clone()));
+    item.insert("objectId".to_string(), AttributeValue::S(record.object_id.clone()));
+    item.insert("objectType".to_string(), AttributeValue::S(record.object_type.clone()));
+    item.insert("requestType".to_string(), AttributeValue::S(record.request_type.clone()));
+    item.insert("recordedAt".to_string(), AttributeValue::N(record.recorded_at.to_string()));
+    let optional = [
+        ("status", record.status.clone().map(AttributeValue::S)),
+        ("amount", record.amount.map(|amount| AttributeValue::N(amount.to_string()))),
+        ("currency", record.currency.clone().map(AttributeValue::S)),
+        ("relatedObjectId", record.related_object_id.clone().map(AttributeValue::S)),
+        ("eventType", record.event_type.clone().map(AttributeValue::S)),
+        ("metadata", record.metadata.as_ref().map(serde_json::to_string).transpose()?.map(AttributeValue::S)),
+    ];
+    for (name, value) in optional {
+        if let Some(value) = value {
+            item.insert(name.to_string(), value);
+        }
+    }
+    Ok(item)
+}
+
+fn from_item(item: &Item) -> Result<TransactionRecord, GatewayError> {
+    let required = |name: &str| {
+        string(item, name).ok_or_else(|| GatewayError::StorageError(format!("Ledger item is missing {}", name)))
+    };
+    Ok(TransactionRecord {
+        store_id: required("storeId")?,
+        object_id: required("objectId")?,
+        object_type: required("objectType")?,
+        request_type: required("requestType")?,
+        status: string(item, "status"),
+        amount: number(item, "amount"),
+        currency: string(item, "currency"),
+        related_object_id: string(item, "relatedObjectId"),
+        event_type: string(item, "eventType"),
+        metadata: string(item, "metadata").map(|metadata| serde_json::from_str(&metadata)).transpose()?,
+        recorded_at: number(item, "recordedAt").unwrap_or(0),
+    })
+}
+
+fn delivery_to_item(delivery: &WebhookDelivery) -> Result<Item, GatewayError> {
+    let mut item = Item::new();
+    item.insert("storeId".to_string(), AttributeValue::S(delivery.store_id.clone()));
+    item.insert("deliveryId".to_string(), AttributeValue::S(delivery.delivery_id.clone()));
+    item.insert("eventId".to_string(), AttributeValue::S(delivery.event_id.clone()));
+    item.insert("eventType".to_string(), AttributeValue::S(delivery.event_type.clone()));
+    item.insert("url".to_string(), AttributeValue::S(delivery.url.clone()));
+    item.insert("payload".to_string(), AttributeValue::S(serde_json::to_string(&delivery.payload)?));
+    item.insert("status".to_string(), AttributeValue::S(delivery.status.clone()));
+    item.insert("attempts".to_string(), AttributeValue::N(delivery.attempts.to_string()));
+    item.insert("createdAt".to_string(), AttributeValue::N(delivery.created_at.to_string()));
+    item.insert("updatedAt".to_string(), AttributeValue::N(delivery.updated_at.to_string()));
+    let optional = [
+        ("lastStatusCode", delivery.last_status_code.map(|code| AttributeValue::N(code.to_string()))),
+        ("lastError", delivery.last_error.clone().map(AttributeValue::S)),
+        ("nextAttemptAt", delivery.next_attempt_at.map(|at| AttributeValue::N(at.to_string()))),
+    ];
+    for (name, value) in optional {
+        if let Some(value) = value {
+            item.insert(name.to_string(), value);
+        }
+    }
+    Ok(item)
+}
+
+fn delivery_from_item(item: &Item) -> Result<WebhookDelivery, GatewayError> {
+    let required = |name: &str| {
+        string(item, name).ok_or_else(|| GatewayError::StorageError(format!("Delivery item is missing {}", name)))
+    };
+    Ok(WebhookDelivery {
+        store_id: required("storeId")?,
+        delivery_id: required("deliveryId")?,
+        event_id: required("eventId")?,
+        event_type: required("eventType")?,
+        url: required("url")?,
+        payload: serde_json::from_str(&required("payload")?)?,
+        status: required("status")?,
+        attempts: number(item, "attempts").unwrap_or(0),
+        last_status_code: number(item, "lastStatusCode"),
+        last_error: string(item, "lastError"),
+        next_attempt_at: number(item, "nextAttemptAt"),
+        created_at: number(item, "createdAt").unwrap_or(0),
+        updated_at: number(item, "updatedAt").unwrap_or(0),
+    })
+}
+
+fn string(item: &Item, name: &str) -> Option<String> {
+    item.get(name).and_then(|value| value.as_s().ok()).cloned()
+}
+
+fn number(item: &Item, name: &str) -> Option<i64> {
+    item.get(name).and_then(|value| value.as_n().ok()).and_then(|n| n.parse().ok())
+}
@@ -0,0 +1,154 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use async_trait::async_trait;
+use crate::errors::GatewayError;
+use crate::models::{TransactionRecord, WebhookDelivery};
+use super::{now_secs, DeliveryStore, ObjectState, RedemptionStore, TransactionStore, WebhookEventStore, DELIVERY_PENDING};
+
+/// Keeps records for the lifetime of the process. Intended for local runs and tests;
+/// in Lambda the history is lost whenever the container is recycled.
+pub struct InMemoryTransactionStore {
+    records: Mutex<HashMap<(String, String), TransactionRecord>>,
+    claimed_events: Mutex<HashMap<(String, String), i64>>,
+    object_states: Mutex<HashMap<(String, String), ObjectState>>,
+    deliveries: Mutex<HashMap<(String, String), WebhookDelivery>>,
+    redemptions: Mutex<HashMap<(String, String), i64>>,
+}
+
+impl InMemoryTransactionStore {
+    pub fn new() -> Self {
+        InMemoryTransactionStore {
+            records: Mutex::new(HashMap::new()),
+            claimed_events: Mutex::new(HashMap::new()),
+            object_states: Mutex::new(HashMap::new()),
+            deliveries: Mutex::new(HashMap::new()),
+            redemptions: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+#[async_trait]
+impl TransactionStore for InMemoryTransactionStore {
+    async fn record(&self, record: &TransactionRecord) -> Result<(), GatewayError> {
+        let mut records = self.records.lock()
+            .map_err(|_| GatewayError::Unexpected("Ledger lock poisoned".to_string()))?;
+        records.insert((record.store_id.clone(), record.object_id.clone()), record.clone());
+        Ok(())
+    }
+
+    async fn get(&self, store_id: &str, object_id: &str) -> Result<Option<TransactionRecord>, GatewayError> {
+        let records = self.records.lock()
+            .map_err(|_| GatewayError::Unexpected("Ledger lock poisoned".to_string()))?;
+        Ok(records.get(&(store_id.to_string(), object_id.to_string())).cloned())
+    }
+
+    async fn list(&self, store_id: &str, limit: usize) -> Result<Vec<TransactionRecord>, GatewayError> {
+        let records = self.records.lock()
+            .map_err(|_| GatewayError::Unexpected("Ledger lock poisoned".to_string()))?;
+        let mut matching: Vec<TransactionRecord> = records.values()
+            .filter(|record| record.store_id == store_id)
+            .cloned()
+            .collect();
+        matching.sort_by_key(|record| std::cmp::Reverse(record.recorded_at));
+        matching.truncate(limit);
+        Ok(matching)
+    }
+}
+
+#[async_trait]
+impl WebhookEventStore for InMemoryTransactionStore {
+    async fn claim_event(&self, store_id: &str, event_id: &str, ttl_secs: i64) -> Result<bool, GatewayError> {
+        let mut claimed = self.claimed_events.lock()
+            .map_err(|_| GatewayError::Unexpected("Ledger lock poisoned".to_string()))?;
+        let now = now_secs();
+        claimed.retain(|_, expires_at| *expires_at > now);
+        let key = (store_id.to_string(), event_id.to_string());
+        if claimed.contains_key(&key) {
+            return Ok(false);
+        }
+        claimed.insert(key, now + ttl_secs);
+        Ok(true)
+    }
+
+    async fn release_event(&self, store_id: &str, event_id: &str) -> Result<(), GatewayError> {
+        let mut claimed = self.claimed_events.lock()
+            .map_err(|_| GatewayError::Unexpected("Ledger lock poisoned".to_string()))?;
+        claimed.remove(&(store_id.to_string(), event_id.to_string()));
+        Ok(())
+    }
+
+    async fn advance_object_state(&self, store_id: &str, state: &ObjectState) -> Result<bool, GatewayError> {
+        let mut states = self.object_states.lock()
+            .map_err(|_| GatewayError::Unexpected("Ledger lock poisoned".to_string()))?;
+        let key = (store_id.to_string(), state.object_id.clone());
+        if states.get(&key).is_some_and(|current| !state.supersedes(current)) {
+            return Ok(false);
+        }
+        states.insert(key, state.clone());
+        Ok(true)
+    }
+}
+
+#[async_trait]
+impl DeliveryStore for InMemoryTransactionStore {
+    async fn save_delivery(&self, delivery: &WebhookDelivery) -> Result<(), GatewayError> {
+        let mut deliveries = self.deliveries.lock()
+            .map_err(|_| GatewayError::Unexpected("Ledger lock poisoned".to_string()))?;
+        deliveries.insert((delivery.store_id.clone(), delivery.delivery_id.clone()), delivery.clone());
+        Ok(())
+    }
+
+    async fn get_delivery(&self, store_id: &str, delivery_id: &str) -> Result<Option<WebhookDelivery>, GatewayError> {
+        let deliveries = self.deliveries.lock()
+            .map_err(|_| GatewayError::Unexpected("Ledger lock poisoned".to_string()))?;
+        Ok(deliveries.get(&(store_id.to_string(), delivery_id.to_string())).cloned())
+    }
+
+    async fn list_deliveries(&self, store_id: &str, limit: usize) -> Result<Vec<WebhookDelivery>, GatewayError> {
+        let deliveries = self.deliveries.lock()
+            .map_err(|_| GatewayError::Unexpected("Ledger lock poisoned".to_string()))?;
+        let mut matching: Vec<WebhookDelivery> = deliveries.values()
+            .filter(|delivery| delivery.store_id == store_id)
+            .cloned()
+            .collect();
+        matching.sort_by_key(|delivery| std::cmp::Reverse(delivery.created_at));
+        matching.truncate(limit);
+        Ok(matching)
+    }
+
+    async fn due_deliveries(&self, store_id: &str, now: i64, limit: usize) -> Result<Vec<WebhookDelivery>, GatewayError> {
+        let deliveries = self.deliveries.lock()
+            .map_err(|_| GatewayError::Unexpected("Ledger lock poisoned".to_string()))?;
+        let mut due: Vec<WebhookDelivery> = deliveries.values()
+            .filter(|delivery| delivery.store_id == store_id && delivery.status == DELIVERY_PENDING)
+            .filter(|delivery| delivery.next_attempt_at.is_some_and(|at| at <= now))
+            .cloned()
+            .collect();
+        due.sort_by_key(|delivery| delivery.next_attempt_at);
+        due.truncate(limit);
+        Ok(due)
+    }
+}
+
+#[async_trait]
+impl RedemptionStore for InMemoryTransactionStore {
+    async fn claim_redemption(&self, store_id: &str, discount_id: &str, limit: Option<i64>) -> Result<bool, GatewayError> {
+        let mut redemptions = self.redemptions.lock()
+            .map_err(|_| GatewayError::Unexpected("Ledger lock poisoned".to_string()))?;
+        let count = redemptions.entry((store_id.to_string(), discount_id.to_string())).or_insert(0);
+        if limit.is_some_and(|limit| *count >= limit) {
+            return Ok(false);
+        }
+        *count += 1;
+        Ok(true)
+    }
+
+    async fn release_redemption(&self, store_id: &str, discount_id: &str) -> Result<(), GatewayError> {
+        let mut redemptions = self.redemptions.lock()
+            .map_err(|_| GatewayError::Unexpected("Ledger lock poisoned".to_string()))?;
+        if let Some(count) = redemptions.get_mut(&(store_id.to_string(), discount_id.to_string())) {
+            *count = (*count - 1).max(0);
+        }
+        Ok(())
+    }
+}
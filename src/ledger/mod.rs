@@ -0,0 +1,291 @@
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use async_trait::async_trait;
+use crate::errors::GatewayError;
+use crate::models::{PaymentRequest, TransactionRecord, WebhookDelivery};
+
+mod dynamodb;
+mod memory;
+mod sqlite;
+
+pub use dynamodb::DynamoDbTransactionStore;
+pub use memory::InMemoryTransactionStore;
+pub use sqlite::SqliteTransactionStore;
+
+const DEFAULT_SQLITE_PATH: &str = "ledger.db";
+
+/// Status of a merchant webhook delivery that still has attempts left.
+pub const DELIVERY_PENDING: &str = "pending";
+
+/// Persistent history of every charge, refund, checkout session and webhook event
+/// handled by the gateway, keyed by store and Stripe object ID.
+#[async_trait]
+pub trait TransactionStore: Send + Sync {
+    /// Inserts the record, replacing any earlier record for the same store and object.
+    async fn record(&self, record: &TransactionRecord) -> Result<(), GatewayError>;
+    async fn get(&self, store_id: &str, object_id: &str) -> Result<Option<TransactionRecord>, GatewayError>;
+    /// Most recent records first.
+    async fn list(&self, store_id: &str, limit: usize) -> Result<Vec<TransactionRecord>, GatewayError>;
+}
+
+/// Memory of webhook deliveries. Stripe delivers events at least once and in no particular
+/// order, so events are claimed by ID before being handled and object status only moves
+/// forward in event time.
+#[async_trait]
+pub trait WebhookEventStore: Send + Sync {
+    /// Returns `false` when the event was already claimed and its claim has not expired.
+    async fn claim_event(&self, store_id: &str, event_id: &str, ttl_secs: i64) -> Result<bool, GatewayError>;
+    /// Drops a claim so a redelivery of an event whose handling failed is processed again.
+    async fn release_event(&self, store_id: &str, event_id: &str) -> Result<(), GatewayError>;
+    /// Stores `status` for the object if the event is newer than the one last applied.
+    /// Stripe timestamps are whole seconds, so on a tie the status further along
+    /// (see [`status_rank`]) wins, and of two equally advanced statuses the one applied
+    /// first is kept. The same event can always be applied again. Returns whether it was applied.
+    async fn advance_object_state(&self, store_id: &str, state: &ObjectState) -> Result<bool, GatewayError>;
+}
+
+/// Log of outbound merchant webhook deliveries, which also holds their retry schedule.
+#[async_trait]
+pub trait DeliveryStore: Send + Sync {
+    /// Inserts the delivery, replacing any earlier version of it.
+    async fn save_delivery(&self, delivery: &WebhookDelivery) -> Result<(), GatewayError>;
+    async fn get_delivery(&self, store_id: &str, delivery_id: &str) -> Result<Option<WebhookDelivery>, GatewayError>;
+    /// Most recent deliveries first.
+    async fn list_deliveries(&self, store_id: &str, limit: usize) -> Result<Vec<WebhookDelivery>, GatewayError>;
+    /// Pending deliveries whose next attempt is due by `now`, earliest first.
+    async fn due_deliveries(&self, store_id: &str, now: i64, limit: usize) -> Result<Vec<WebhookDelivery>, GatewayError>;
+}
+
+/// Count of discounts redeemed by gateway charges. The Charges and Payment Intents APIs
+/// take no discounts, so Stripe's own `times_redeemed` never counts these redemptions.
+#[async_trait]
+pub trait RedemptionStore: Send + Sync {
+    /// Counts one more redemption of the discount unless `limit` redemptions are already
+    /// counted. Returns whether it was counted.
+    async fn claim_redemption(&self, store_id: &str, discount_id: &str, limit: Option<i64>) -> Result<bool, GatewayError>;
+    /// Gives back a redemption whose charge failed.
+    async fn release_redemption(&self, store_id: &str, discount_id: &str) -> Result<(), GatewayError>;
+}
+
+#[derive(Debug, Clone)]
+pub struct ObjectState {
+    pub object_id: String,
+    pub status: String,
+    pub event_id: String,
+    pub event_created: i64,
+}
+
+impl ObjectState {
+    /// Whether this state should replace `current`, the state last applied to the object.
+    pub fn supersedes(&self, current: &ObjectState) -> bool {
+        self.event_created > current.event_created
+            || self.event_id == current.event_id
+            || (self.event_created == current.event_created && status_rank(&self.status) > status_rank(&current.status))
+    }
+}
+
+/// How far along its lifecycle a Stripe object with this status is. Events from the same
+/// second are ordered by it, so a status never gives way to one it can only have come from.
+pub fn status_rank(status: &str) -> u8 {
+    match status {
+        "processing" | "requires_capture" | "under_review" | "warning_under_review" => 1,
+        "succeeded" | "failed" | "canceled" | "refunded" | "paid" | "void" | "uncollectible"
+        | "complete" | "expired" | "won" | "lost" | "warning_closed" => 2,
+        _ => 0,
+    }
+}
+
+/// The storage backends selected for this process.
+pub struct Stores {
+    pub transactions: Arc<dyn TransactionStore>,
+    pub webhook_events: Arc<dyn WebhookEventStore>,
+    pub deliveries: Arc<dyn DeliveryStore>,
+    pub redemptions: Arc<dyn RedemptionStore>,
+}
+
+/// Selects the backend with `LEDGER_BACKEND`: `dynamodb` (tables from `LEDGER_TABLE`,
+/// `WEBHOOK_STATE_TABLE` and optionally `WEBHOOK_DELIVERY_TABLE`), `sqlite` (file from
+/// `LEDGER_SQLITE_PATH`) or `memory` (the default).
+pub async fn from_env() -> Result<Stores, GatewayError> {
+    let backend = std::env::var("LEDGER_BACKEND").unwrap_or_else(|_| "memory".to_string());
+    match backend.to_lowercase().as_str() {
+        "dynamodb" => {
+            let table = required_env("LEDGER_TABLE")?;
+            let state_table = required_env("WEBHOOK_STATE_TABLE")?;
+            let index = std::env::var("LEDGER_TIME_INDEX").ok();
+            let delivery_table = std::env::var("WEBHOOK_DELIVERY_TABLE").ok();
+            Ok(Stores::from(Arc::new(DynamoDbTransactionStore::new(table, index, state_table, delivery_table).await)))
+        }
+        "sqlite" => {
+            let path = std::env::var("LEDGER_SQLITE_PATH").unwrap_or_else(|_| DEFAULT_SQLITE_PATH.to_string());
+            Ok(Stores::from(Arc::new(SqliteTransactionStore::open(&path)?)))
+        }
+        "memory" => Ok(Stores::from(Arc::new(InMemoryTransactionStore::new()))),
+        other => Err(GatewayError::Unexpected(format!("Unknown ledger backend: {}", other))),
+    }
+}
+
+impl<T: TransactionStore + WebhookEventStore + DeliveryStore + RedemptionStore + 'static> From<Arc<T>> for Stores {
+    fn from(store: Arc<T>) -> Self {
+        Stores {
+            transactions: store.clone(),
+            webhook_events: store.clone(),
+            deliveries: store.clone(),
+            redemptions: store,
+        }
+    }
+}
+
+fn required_env(name: &str) -> Result<String, GatewayError> {
+    std::env::var(name)
+        .map_err(|_| GatewayError::Unexpected(format!("{} is required for the dynamodb ledger", name)))
+}
+
+impl TransactionRecord {
+    pub fn new(request: &PaymentRequest, object_type: &str, object_id: String) -> Self {
+        TransactionRecord {
+            store_id: request.store_id.clone(),
+            object_id,
+            object_type: object_type.to_string(),
+            request_type: request.request_type.to_uppercase(),
+            status: None,
+            amount: None,
+            currency: None,
+            related_object_id: None,
+            event_type: None,
+            metadata: request.metadata.clone(),
+            recorded_at: now_secs(),
+        }
+    }
+}
+
+pub(crate) fn now_secs() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use super::*;
+
+    fn record(store_id: &str, object_id: &str, status: &str, recorded_at: i64) -> TransactionRecord {
+        serde_json::from_value(json!({
+            "storeId": store_id,
+            "objectId": object_id,
+            "objectType": "charge",
+            "requestType": "CHARGE",
+            "status": status,
+            "amount": 1000,
+            "currency": "usd",
+            "recordedAt": recorded_at,
+        })).unwrap()
+    }
+
+    fn state(object_id: &str, status: &str, event_id: &str, event_created: i64) -> ObjectState {
+        ObjectState {
+            object_id: object_id.to_string(),
+            status: status.to_string(),
+            event_id: event_id.to_string(),
+            event_created,
+        }
+    }
+
+    fn delivery(delivery_id: &str, status: &str, next_attempt_at: Option<i64>, created_at: i64) -> WebhookDelivery {
+        serde_json::from_value(json!({
+            "deliveryId": delivery_id,
+            "storeId": "store-a",
+            "eventId": "evt_1",
+            "eventType": "charge.succeeded",
+            "url": "https://merchant.example.com/webhooks",
+            "payload": {},
+            "status": status,
+            "attempts": 1,
+            "nextAttemptAt": next_attempt_at,
+            "createdAt": created_at,
+            "updatedAt": created_at,
+        })).unwrap()
+    }
+
+    /// Behaviour every backend has to share. DynamoDB needs a live table, so only the local
+    /// backends run it.
+    async fn store_contract<S>(store: S)
+    where
+        S: TransactionStore + WebhookEventStore + DeliveryStore + RedemptionStore,
+    {
+        // A record replaces the earlier record of the same object.
+        store.record(&record("store-a", "ch_1", "pending", 100)).await.unwrap();
+        store.record(&record("store-a", "ch_1", "succeeded", 200)).await.unwrap();
+        let stored = store.get("store-a", "ch_1").await.unwrap().unwrap();
+        assert_eq!(stored.status.as_deref(), Some("succeeded"));
+
+        // History is scoped to the store, most recent first.
+        store.record(&record("store-a", "ch_2", "succeeded", 400)).await.unwrap();
+        store.record(&record("store-a", "ch_3", "succeeded", 300)).await.unwrap();
+        store.record(&record("store-b", "ch_4", "succeeded", 500)).await.unwrap();
+        let recent: Vec<String> = store.list("store-a", 2).await.unwrap().into_iter().map(|r| r.object_id).collect();
+        assert_eq!(recent, ["ch_2", "ch_3"]);
+        assert_eq!(store.list("store-a", 10).await.unwrap().len(), 3);
+        assert!(store.get("store-b", "ch_1").await.unwrap().is_none());
+
+        // An event is claimed once per store, until the claim is released or expires.
+        assert!(store.claim_event("store-a", "evt_1", 3600).await.unwrap());
+        assert!(!store.claim_event("store-a", "evt_1", 3600).await.unwrap());
+        assert!(store.claim_event("store-b", "evt_1", 3600).await.unwrap());
+        store.release_event("store-a", "evt_1").await.unwrap();
+        assert!(store.claim_event("store-a", "evt_1", 3600).await.unwrap());
+        assert!(store.claim_event("store-a", "evt_2", -1).await.unwrap());
+        assert!(store.claim_event("store-a", "evt_2", 3600).await.unwrap());
+
+        // Object state never moves back to an older event.
+        assert!(store.advance_object_state("store-a", &state("ch_1", "succeeded", "evt_2", 200)).await.unwrap());
+        assert!(!store.advance_object_state("store-a", &state("ch_1", "pending", "evt_1", 100)).await.unwrap());
+        assert!(store.advance_object_state("store-a", &state("ch_1", "refunded", "evt_3", 300)).await.unwrap());
+        // Of two equally advanced statuses from the same second the first applied is kept, but
+        // the same event can be applied again.
+        assert!(!store.advance_object_state("store-a", &state("ch_1", "failed", "evt_4", 300)).await.unwrap());
+        assert!(store.advance_object_state("store-a", &state("ch_1", "refunded", "evt_3", 300)).await.unwrap());
+        // Within a second a status moves forward in its lifecycle, whichever event comes first.
+        assert!(store.advance_object_state("store-a", &state("pi_1", "requires_capture", "evt_5", 400)).await.unwrap());
+        assert!(store.advance_object_state("store-a", &state("pi_1", "succeeded", "evt_6", 400)).await.unwrap());
+        assert!(store.advance_object_state("store-a", &state("pi_2", "succeeded", "evt_7", 400)).await.unwrap());
+        assert!(!store.advance_object_state("store-a", &state("pi_2", "requires_capture", "evt_8", 400)).await.unwrap());
+
+        // Deliveries are listed newest first; only pending ones that are due come up for retry,
+        // earliest first.
+        store.save_delivery(&delivery("whd_1", DELIVERY_PENDING, Some(150), 100)).await.unwrap();
+        store.save_delivery(&delivery("whd_2", DELIVERY_PENDING, Some(120), 200)).await.unwrap();
+        store.save_delivery(&delivery("whd_3", DELIVERY_PENDING, Some(900), 300)).await.unwrap();
+        store.save_delivery(&delivery("whd_4", "succeeded", None, 400)).await.unwrap();
+        let listed: Vec<String> = store.list_deliveries("store-a", 10).await.unwrap().into_iter().map(|d| d.delivery_id).collect();
+        assert_eq!(listed, ["whd_4", "whd_3", "whd_2", "whd_1"]);
+        let due: Vec<String> = store.due_deliveries("store-a", 500, 10).await.unwrap().into_iter().map(|d| d.delivery_id).collect();
+        assert_eq!(due, ["whd_2", "whd_1"]);
+        store.save_delivery(&delivery("whd_1", "succeeded", None, 100)).await.unwrap();
+        let saved = store.get_delivery("store-a", "whd_1").await.unwrap().unwrap();
+        assert_eq!(saved.status, "succeeded");
+        assert!(store.get_delivery("store-b", "whd_1").await.unwrap().is_none());
+
+        // Redemptions are counted per store up to the limit, and can be given back.
+        assert!(store.claim_redemption("store-a", "coupon:SUMMER", Some(2)).await.unwrap());
+        assert!(store.claim_redemption("store-a", "coupon:SUMMER", Some(2)).await.unwrap());
+        assert!(!store.claim_redemption("store-a", "coupon:SUMMER", Some(2)).await.unwrap());
+        assert!(store.claim_redemption("store-b", "coupon:SUMMER", Some(2)).await.unwrap());
+        store.release_redemption("store-a", "coupon:SUMMER").await.unwrap();
+        assert!(store.claim_redemption("store-a", "coupon:SUMMER", Some(2)).await.unwrap());
+        assert!(store.claim_redemption("store-a", "coupon:SUMMER", None).await.unwrap());
+        assert!(!store.claim_redemption("store-a", "coupon:WINTER", Some(0)).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn memory_store_meets_the_contract() {
+        store_contract(InMemoryTransactionStore::new()).await;
+    }
+
+    #[tokio::test]
+    async fn sqlite_store_meets_the_contract() {
+        store_contract(SqliteTransactionStore::open(":memory:").unwrap()).await;
+    }
+}
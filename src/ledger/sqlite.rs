@@ -0,0 +1,273 @@
+use std::sync::{Arc, Mutex};
+use async_trait::async_trait;
+use rusqlite::{params, Connection, OptionalExtension};
+use crate::errors::GatewayError;
+use crate::models::{TransactionRecord, WebhookDelivery};
+use super::{now_secs, DeliveryStore, ObjectState, RedemptionStore, TransactionStore, WebhookEventStore, DELIVERY_PENDING};
+
+/// Single-file ledger for running the gateway locally.
+pub struct SqliteTransactionStore {
+    connection: Arc<Mutex<Connection>>,
+}
+
+impl SqliteTransactionStore {
+    pub fn open(path: &str) -> Result<Self, GatewayError> {
+        let connection = Connection::open(path).map_err(storage_error)?;
+        connection.execute_batch(
+            "CREATE TABLE IF NOT EXISTS transactions (
+                store_id TEXT NOT NULL,
+                object_id TEXT NOT NULL,
+                recorded_at INTEGER NOT NULL,
+                data TEXT NOT NULL,
+                PRIMARY KEY (store_id, object_id)
+            );
+            CREATE INDEX IF NOT EXISTS transactions_by_time ON transactions (store_id, recorded_at);
+            CREATE TABLE IF NOT EXISTS webhook_events (
+                store_id TEXT NOT NULL,
+                event_id TEXT NOT NULL,
+                expires_at INTEGER NOT NULL,
+                PRIMARY KEY (store_id, event_id)
+            );
+            CREATE TABLE IF NOT EXISTS object_states (
+                store_id TEXT NOT NULL,
+                object_id TEXT NOT NULL,
+                status TEXT NOT NULL,
+                event_id TEXT NOT NULL,
+                event_created INTEGER NOT NULL,
+                PRIMARY KEY (store_id, object_id)
+            );
+            CREATE TABLE IF NOT EXISTS webhook_deliveries (
+                store_id TEXT NOT NULL,
+                delivery_id TEXT NOT NULL,
+                status TEXT NOT NULL,
+                next_attempt_at INTEGER,
+                created_at INTEGER NOT NULL,
+                data TEXT NOT NULL,
+                PRIMARY KEY (store_id, delivery_id)
+            );
+            CREATE INDEX IF NOT EXISTS webhook_deliveries_by_time ON webhook_deliveries (store_id, created_at);
+            CREATE TABLE IF NOT EXISTS discount_redemptions (
+                store_id TEXT NOT NULL,
+                discount_id TEXT NOT NULL,
+                redemptions INTEGER NOT NULL,
+                PRIMARY KEY (store_id, discount_id)
+            );",
+        ).map_err(storage_error)?;
+        Ok(SqliteTransactionStore {
+            connection: Arc::new(Mutex::new(connection)),
+        })
+    }
+
+    /// Runs `operation` on the blocking thread pool so SQLite I/O never stalls the runtime.
+    async fn with_connection<T, F>(&self, operation: F) -> Result<T, GatewayError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&Connection) -> Result<T, GatewayError> + Send + 'static,
+    {
+        let connection = self.connection.clone();
+        tokio::task::spawn_blocking(move || {
+            let connection = connection.lock()
+                .map_err(|_| GatewayError::StorageError("SQLite connection lock poisoned".to_string()))?;
+            operation(&connection)
+        })
+        .await
+        .map_err(|e| GatewayError::StorageError(e.to_string()))?
+    }
+}
+
+#[async_trait]
+impl TransactionStore for SqliteTransactionStore {
+    async fn record(&self, record: &TransactionRecord) -> Result<(), GatewayError> {
+        let data = serde_json::to_string(record)?;
+        let (store_id, object_id, recorded_at) = (record.store_id.clone(), record.object_id.clone(), record.recorded_at);
+        self.with_connection(move |connection| {
+            connection.execute(
+                "INSERT OR REPLACE INTO transactions (store_id, object_id, recorded_at, data) VALUES (?1, ?2, ?3, ?4)",
+                params![store_id, object_id, recorded_at, data],
+            ).map_err(storage_error)?;
+            Ok(())
+        }).await
+    }
+
+    async fn get(&self, store_id: &str, object_id: &str) -> Result<Option<TransactionRecord>, GatewayError> {
+        let (store_id, object_id) = (store_id.to_string(), object_id.to_string());
+        let data: Option<String> = self.with_connection(move |connection| {
+            connection.query_row(
+                "SELECT data FROM transactions WHERE store_id = ?1 AND object_id = ?2",
+                params![store_id, object_id],
+                |row| row.get(0),
+            ).optional().map_err(storage_error)
+        }).await?;
+        data.map(|data| serde_json::from_str(&data).map_err(GatewayError::from)).transpose()
+    }
+
+    async fn list(&self, store_id: &str, limit: usize) -> Result<Vec<TransactionRecord>, GatewayError> {
+        let store_id = store_id.to_string();
+        let rows: Vec<String> = self.with_connection(move |connection| {
+            let mut statement = connection.prepare(
+                "SELECT data FROM transactions WHERE store_id = ?1 ORDER BY recorded_at DESC LIMIT ?2",
+            ).map_err(storage_error)?;
+            let rows = statement.query_map(params![store_id, limit as i64], |row| row.get(0))
+                .map_err(storage_error)?;
+            rows.collect::<Result<Vec<String>, _>>().map_err(storage_error)
+        }).await?;
+        rows.iter()
+            .map(|data| serde_json::from_str(data).map_err(GatewayError::from))
+            .collect()
+    }
+}
+
+#[async_trait]
+impl WebhookEventStore for SqliteTransactionStore {
+    async fn claim_event(&self, store_id: &str, event_id: &str, ttl_secs: i64) -> Result<bool, GatewayError> {
+        let (store_id, event_id) = (store_id.to_string(), event_id.to_string());
+        let now = now_secs();
+        self.with_connection(move |connection| {
+            connection.execute("DELETE FROM webhook_events WHERE expires_at <= ?1", params![now])
+                .map_err(storage_error)?;
+            let inserted = connection.execute(
+                "INSERT OR IGNORE INTO webhook_events (store_id, event_id, expires_at) VALUES (?1, ?2, ?3)",
+                params![store_id, event_id, now + ttl_secs],
+            ).map_err(storage_error)?;
+            Ok(inserted == 1)
+        }).await
+    }
+
+    async fn release_event(&self, store_id: &str, event_id: &str) -> Result<(), GatewayError> {
+        let (store_id, event_id) = (store_id.to_string(), event_id.to_string());
+        self.with_connection(move |connection| {
+            connection.execute(
+                "DELETE FROM webhook_events WHERE store_id = ?1 AND event_id = ?2",
+                params![store_id, event_id],
+            ).map_err(storage_error)?;
+            Ok(())
+        }).await
+    }
+
+    async fn advance_object_state(&self, store_id: &str, state: &ObjectState) -> Result<bool, GatewayError> {
+        let store_id = store_id.to_string();
+        let state = state.clone();
+        self.with_connection(move |connection| {
+            let current = connection.query_row(
+                "SELECT status, event_id, event_created FROM object_states WHERE store_id = ?1 AND object_id = ?2",
+                params![store_id, state.object_id],
+                |row| Ok(ObjectState {
+                    object_id: state.object_id.clone(),
+                    status: row.get(0)?,
+                    event_id: row.get(1)?,
+                    event_created: row.get(2)?,
+                }),
+            ).optional().map_err(storage_error)?;
+            if current.is_some_and(|current| !state.supersedes(&current)) {
+                return Ok(false);
+            }
+            connection.execute(
+                "INSERT OR REPLACE INTO object_states (store_id, object_id, status, event_id, event_created)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![store_id, state.object_id, state.status, state.event_id, state.event_created],
+            ).map_err(storage_error)?;
+            Ok(true)
+        }).await
+    }
+}
+
+#[async_trait]
+impl DeliveryStore for SqliteTransactionStore {
+    async fn save_delivery(&self, delivery: &WebhookDelivery) -> Result<(), GatewayError> {
+        let data = serde_json::to_string(delivery)?;
+        let delivery = delivery.clone();
+        self.with_connection(move |connection| {
+            connection.execute(
+                "INSERT OR REPLACE INTO webhook_deliveries (store_id, delivery_id, status, next_attempt_at, created_at, data)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                params![
+                    delivery.store_id,
+                    delivery.delivery_id,
+                    delivery.status,
+                    delivery.next_attempt_at,
+                    delivery.created_at,
+                    data
+                ],
+            ).map_err(storage_error)?;
+            Ok(())
+        }).await
+    }
+
+    async fn get_delivery(&self, store_id: &str, delivery_id: &str) -> Result<Option<WebhookDelivery>, GatewayError> {
+        let (store_id, delivery_id) = (store_id.to_string(), delivery_id.to_string());
+        let data: Option<String> = self.with_connection(move |connection| {
+            connection.query_row(
+                "SELECT data FROM webhook_deliveries WHERE store_id = ?1 AND delivery_id = ?2",
+                params![store_id, delivery_id],
+                |row| row.get(0),
+            ).optional().map_err(storage_error)
+        }).await?;
+        data.map(|data| serde_json::from_str(&data).map_err(GatewayError::from)).transpose()
+    }
+
+    async fn list_deliveries(&self, store_id: &str, limit: usize) -> Result<Vec<WebhookDelivery>, GatewayError> {
+        let store_id = store_id.to_string();
+        let rows: Vec<String> = self.with_connection(move |connection| {
+            let mut statement = connection.prepare(
+                "SELECT data FROM webhook_deliveries WHERE store_id = ?1 ORDER BY created_at DESC LIMIT ?2",
+            ).map_err(storage_error)?;
+            let rows = statement.query_map(params![store_id, limit as i64], |row| row.get(0))
+                .map_err(storage_error)?;
+            rows.collect::<Result<Vec<String>, _>>().map_err(storage_error)
+        }).await?;
+        rows.iter()
+            .map(|data| serde_json::from_str(data).map_err(GatewayError::from))
+            .collect()
+    }
+
+    async fn due_deliveries(&self, store_id: &str, now: i64, limit: usize) -> Result<Vec<WebhookDelivery>, GatewayError> {
+        let store_id = store_id.to_string();
+        let rows: Vec<String> = self.with_connection(move |connection| {
+            let mut statement = connection.prepare(
+                "SELECT data FROM webhook_deliveries
+                 WHERE store_id = ?1 AND status = ?2 AND next_attempt_at <= ?3
+                 ORDER BY next_attempt_at LIMIT ?4",
+            ).map_err(storage_error)?;
+            let rows = statement.query_map(params![store_id, DELIVERY_PENDING, now, limit as i64], |row| row.get(0))
+                .map_err(storage_error)?;
+            rows.collect::<Result<Vec<String>, _>>().map_err(storage_error)
+        }).await?;
+        rows.iter()
+            .map(|data| serde_json::from_str(data).map_err(GatewayError::from))
+            .collect()
+    }
+}
+
+#[async_trait]
+impl RedemptionStore for SqliteTransactionStore {
+    async fn claim_redemption(&self, store_id: &str, discount_id: &str, limit: Option<i64>) -> Result<bool, GatewayError> {
+        let (store_id, discount_id) = (store_id.to_string(), discount_id.to_string());
+        self.with_connection(move |connection| {
+            let changed = connection.execute(
+                "INSERT INTO discount_redemptions (store_id, discount_id, redemptions)
+                 SELECT ?1, ?2, 1 WHERE ?3 IS NULL OR ?3 > 0
+                 ON CONFLICT (store_id, discount_id) DO UPDATE
+                 SET redemptions = redemptions + 1
+                 WHERE ?3 IS NULL OR discount_redemptions.redemptions < ?3",
+                params![store_id, discount_id, limit],
+            ).map_err(storage_error)?;
+            Ok(changed > 0)
+        }).await
+    }
+
+    async fn release_redemption(&self, store_id: &str, discount_id: &str) -> Result<(), GatewayError> {
+        let (store_id, discount_id) = (store_id.to_string(), discount_id.to_string());
+        self.with_connection(move |connection| {
+            connection.execute(
+                "UPDATE discount_redemptions SET redemptions = redemptions - 1
+                 WHERE store_id = ?1 AND discount_id = ?2 AND redemptions > 0",
+                params![store_id, discount_id],
+            ).map_err(storage_error)?;
+            Ok(())
+        }).await
+    }
+}
+
+fn storage_error(error: rusqlite::Error) -> GatewayError {
+    GatewayError::StorageError(error.to_string())
+}
@@ -1,81 +1,258 @@
+use std::sync::Arc;
+use std::time::Instant;
 use lambda_runtime::{run, service_fn, Error as LambdaError, LambdaEvent};
 use serde_json::{json, Value};
-use log::{info, error};
-use env_logger::Env;
-use crate::services::SecretsService;
+use opentelemetry_sdk::trace::SdkTracerProvider;
+use tracing::{error, field, info, Instrument, Span};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use crate::auth::Authenticator;
+use crate::ledger::Stores;
+use crate::publisher::EventRouter;
+use crate::errors::GatewayError;
+use crate::services::{SecretsService, StoreNamespace};
 use crate::parser::JsonRequestParser;
-use crate::models::{PaymentRequest, ErrorResponse};
+use crate::metrics::{MetricContext, Unit};
+use crate::models::{ErrorResponse, PaymentRequest};
 use crate::factory::{PaymentProcessorFactory, PaymentProcessor};
-use crate::errors::GatewayError;
+use crate::merchant_webhooks::MerchantNotifier;
 
+mod auth;
 mod errors;
 mod models;
 mod services;
 mod parser;
 mod processors;
+mod publisher;
+mod redact;
 mod factory;
+mod ledger;
+mod logging;
+mod merchant_webhooks;
+mod metrics;
+mod stripe;
+mod telemetry;
+
+const SCHEDULED_RETRY_REQUEST_TYPE: &str = "SCHEDULED_WEBHOOK_RETRY";
+/// Due deliveries attempted per store on each scheduled run.
+const SCHEDULED_RETRY_LIMIT: usize = 100;
 
 #[tokio::main]
 async fn main() -> Result<(), LambdaError> {
-    env_logger::from_env(Env::default().default_filter_or("info")).init();
-    run(service_fn(function_handler)).await
+    let tracer_provider = telemetry::tracer_provider()?;
+    logging::init(tracer_provider.as_ref());
+    let state = Arc::new(GatewayState {
+        authenticator: Authenticator::from_env()?,
+        secrets_service: SecretsService::new(StoreNamespace::from_env()?).await?,
+        stores: Arc::new(ledger::from_env().await?),
+        router: Arc::new(EventRouter::from_env().await?),
+        http_client: reqwest::Client::new(),
+        tracer_provider,
+    });
+    run(service_fn(move |event| {
+        let state = state.clone();
+        async move { function_handler(event, &state).await }
+    })).await
 }
 
-async fn function_handler(event: LambdaEvent<Value>) -> Result<Value, LambdaError> {
-    info!("Received event: {:?}", event);
+/// Shared across invocations of a warm Lambda container.
+struct GatewayState {
+    authenticator: Authenticator,
+    secrets_service: SecretsService,
+    stores: Arc<Stores>,
+    router: Arc<EventRouter>,
+    http_client: reqwest::Client,
+    tracer_provider: Option<SdkTracerProvider>,
+}
+
+async fn function_handler(event: LambdaEvent<Value>, state: &GatewayState) -> Result<Value, LambdaError> {
+    let span = tracing::info_span!(
+        "invocation",
+        otel.kind = "server",
+        request_id = %event.context.request_id,
+        caller_id = field::Empty,
+        store_id = field::Empty,
+        request_type = field::Empty,
+        idempotency_key = field::Empty,
+    );
+    let parent = telemetry::parent_context(&event.payload, event.context.xray_trace_id.as_deref());
+    if let Err(e) = span.set_parent(parent) {
+        tracing::debug!("Failed to attach parent trace context: {}", e);
+    }
+
+    let result = handle_invocation(event, state).instrument(span).await;
+    if let Some(provider) = &state.tracer_provider {
+        telemetry::flush(provider).await;
+    }
+    result
+}
+
+async fn handle_invocation(event: LambdaEvent<Value>, state: &GatewayState) -> Result<Value, LambdaError> {
+    info!("Received event");
+    if is_scheduled_event(&event.payload) {
+        return Ok(retry_merchant_webhooks(state).await);
+    }
+    let authenticator = &state.authenticator;
+    let caller = match authenticator.authenticate(&event.payload) {
+        Ok(caller) => caller,
+        Err(e) => return Ok(auth_error_response(e)),
+    };
+    Span::current().record("caller_id", caller.caller_id.as_str());
+
     let parser = JsonRequestParser::new();
     let request = match parser.parse(event.payload) {
         Ok(req) => req,
-        Err(e) => return Ok(json!({
-            "statusCode": 400,
-            "body": ErrorResponse {
-                status: "error".to_string(),
-                message: format!("Invalid request: {}", e),
-                status_code: 400,
-            }
-        })),
+        Err(e) => return Ok(invalid_request_response(e)),
     };
 
-    let secrets_service = match SecretsService::new().await {
-        Ok(service) => service,
-        Err(e) => return Ok(json!({
-            "statusCode": 500,
-            "body": ErrorResponse {
-                status: "error".to_string(),
-                message: format!("Failed to initialize secrets service: {}", e),
-                status_code: 500,
-            }
-        })),
+    let span = Span::current();
+    span.record("store_id", request.store_id.as_str());
+    span.record("request_type", request.request_type.to_uppercase().as_str());
+    if let Some(idempotency_key) = &request.idempotency_key {
+        span.record("idempotency_key", idempotency_key.as_str());
+    }
+
+    if let Err(e) = authenticator.authorize(&caller, &request) {
+        return Ok(auth_error_response(e));
+    }
+    info!(amount = ?request.amount, currency = ?request.currency, "Authorized request");
+
+    let context = MetricContext {
+        store_id: request.store_id.clone(),
+        request_type: request.request_type.to_uppercase(),
     };
+    let started = Instant::now();
+    let response = metrics::scope(context, process_request(&request, state)).await;
+
+    let status_code = response["statusCode"].as_i64().unwrap_or(500).to_string();
+    metrics::count("Requests", &[("StatusCode", &status_code)]);
+    metrics::put("Latency", started.elapsed().as_millis() as f64, Unit::Milliseconds, &[]);
+    if !status_code.starts_with('2') {
+        metrics::count("Errors", &[]);
+    }
+    Ok(response)
+}
 
-    let api_key = match secrets_service.get_secret(&request.store_id).await {
-        Ok(key) => key,
-        Err(e) => return Ok(json!({
-            "statusCode": 500,
-            "body": ErrorResponse {
-                status: "error".to_string(),
-                message: format!("Failed to retrieve API key: {}", e),
-                status_code: 500,
+/// EventBridge schedules invoke the function directly with an `aws.events` payload, which
+/// API Gateway requests cannot produce. The only thing a schedule can do is run due retries.
+fn is_scheduled_event(payload: &Value) -> bool {
+    payload["source"] == "aws.events" && payload["detail-type"] == "Scheduled Event"
+}
+
+/// Runs due merchant webhook retries for every store listed in `MERCHANT_WEBHOOK_RETRY_STORES`
+/// (comma-separated). A store that cannot be retried is logged, and the others still run.
+async fn retry_merchant_webhooks(state: &GatewayState) -> Value {
+    let store_ids = std::env::var("MERCHANT_WEBHOOK_RETRY_STORES").unwrap_or_default();
+    let mut retried = 0;
+    let mut failed_stores = Vec::new();
+    for store_id in store_ids.split(',').map(str::trim).filter(|store_id| !store_id.is_empty()) {
+        let context = MetricContext {
+            store_id: store_id.to_string(),
+            request_type: SCHEDULED_RETRY_REQUEST_TYPE.to_string(),
+        };
+        match metrics::scope(context, retry_store_webhooks(store_id, state)).await {
+            Ok(count) => retried += count,
+            Err(e) => {
+                error!(store_id, "Failed to retry merchant webhooks: {}", redact::secrets(&e.to_string()));
+                failed_stores.push(store_id.to_string());
             }
-        })),
+        }
+    }
+    info!(retried, failed = failed_stores.len(), "Ran scheduled merchant webhook retries");
+    json!({
+        "statusCode": 200,
+        "body": {
+            "status": "success",
+            "retried": retried,
+            "failedStores": failed_stores,
+        }
+    })
+}
+
+async fn retry_store_webhooks(store_id: &str, state: &GatewayState) -> Result<usize, GatewayError> {
+    let secret = state.secrets_service.get_secret(store_id).await?;
+    let notifier = MerchantNotifier::new(
+        secret.merchant_webhooks,
+        state.stores.deliveries.clone(),
+        state.http_client.clone(),
+    );
+    Ok(notifier.retry_due(store_id, SCHEDULED_RETRY_LIMIT).await?.len())
+}
+
+async fn process_request(request: &PaymentRequest, state: &GatewayState) -> Value {
+    let secret = match state.secrets_service.get_secret(&request.store_id).await {
+        Ok(secret) => secret,
+        Err(e) => return secret_error_response(e),
     };
 
-    let factory = PaymentProcessorFactory::new(api_key);
-    match factory.process_payment(&request).await {
-        Ok(response) => Ok(json!({
+    let factory = PaymentProcessorFactory::new(
+        secret,
+        state.stores.clone(),
+        state.router.clone(),
+        state.http_client.clone(),
+    );
+    match factory.process_payment(request).await {
+        Ok(response) => json!({
             "statusCode": response["statusCode"].as_i64().unwrap_or(500),
             "body": response
-        })),
+        }),
         Err(e) => {
-            error!("Error processing request: {}", e);
-            Ok(json!({
+            error!("Error processing request: {}", redact::secrets(&e.to_string()));
+            json!({
                 "statusCode": 500,
                 "body": ErrorResponse {
                     status: "error".to_string(),
-                    message: e.to_string(),
+                    message: redact::secrets(&e.to_string()),
                     status_code: 500,
                 }
-            }))
+            })
         }
     }
-}
\ No newline at end of file
+}
+
+fn invalid_request_response(error: GatewayError) -> Value {
+    metrics::count("Requests", &[("StatusCode", "400")]);
+    json!({
+        "statusCode": 400,
+        "body": ErrorResponse {
+            status: "error".to_string(),
+            message: format!("Invalid request: {}", error),
+            status_code: 400,
+        }
+    })
+}
+
+fn auth_error_response(error: GatewayError) -> Value {
+    let status_code = match error {
+        GatewayError::Forbidden(_) => 403,
+        _ => 401,
+    };
+    error!("Rejected request: {}", error);
+    metrics::count("Requests", &[("StatusCode", &status_code.to_string())]);
+    json!({
+        "statusCode": status_code,
+        "body": ErrorResponse {
+            status: "error".to_string(),
+            message: error.to_string(),
+            status_code,
+        }
+    })
+}
+
+fn secret_error_response(error: GatewayError) -> Value {
+    let (status_code, message) = match &error {
+        GatewayError::InvalidRequest(_) => (400, error.to_string()),
+        GatewayError::UnknownStore(_) => (404, error.to_string()),
+        _ => {
+            error!("Failed to retrieve API key: {}", error);
+            (500, "Failed to retrieve API key".to_string())
+        }
+    };
+    json!({
+        "statusCode": status_code,
+        "body": ErrorResponse {
+            status: "error".to_string(),
+            message,
+            status_code,
+        }
+    })
+}
@@ -0,0 +1,344 @@
+use std::sync::Arc;
+use std::time::Duration;
+use hmac::{Hmac, Mac};
+use reqwest::Client as HttpClient;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use uuid::Uuid;
+use crate::errors::GatewayError;
+use crate::ledger::{now_secs, DeliveryStore, DELIVERY_PENDING};
+use crate::metrics;
+use crate::models::{TransactionRecord, WebhookDelivery};
+use crate::publisher::event_type_matches;
+use crate::redact;
+
+const DELIVERY_SUCCEEDED: &str = "succeeded";
+const DELIVERY_FAILED: &str = "failed";
+const MAX_ATTEMPTS: i64 = 8;
+const RETRY_BASE_DELAY_SECS: i64 = 60;
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A store's outbound webhook subscriptions, read from the `merchantWebhooks` field of its
+/// secret. Deliberately not `Debug`, as it holds the signing secret.
+#[derive(Deserialize, Clone)]
+pub struct MerchantWebhookConfig {
+    #[serde(rename = "signingSecret")]
+    signing_secret: String,
+    #[serde(default)]
+    endpoints: Vec<MerchantEndpoint>,
+}
+
+#[derive(Deserialize, Clone)]
+struct MerchantEndpoint {
+    url: String,
+    /// Same patterns as event routes: exact, `prefix*` or `*`; empty means everything.
+    #[serde(default)]
+    events: Vec<String>,
+}
+
+/// The body POSTed to merchant endpoints.
+#[derive(Serialize)]
+struct GatewayEvent<'a> {
+    id: String,
+    #[serde(rename = "type")]
+    event_type: &'a str,
+    created: i64,
+    #[serde(rename = "storeId")]
+    store_id: &'a str,
+    data: &'a TransactionRecord,
+}
+
+/// Notifies a store's endpoints when one of its charges, payment intents, refunds, disputes,
+/// invoices or checkout sessions changes status, as `<objectType>.<status>` events
+/// (e.g. `charge.succeeded`).
+///
+/// Every request is signed with an `X-Gateway-Signature: t=<timestamp>,v1=<signature>`
+/// header, the hex HMAC-SHA256 of `<timestamp>.<body>` under the store's signing secret.
+/// Failed deliveries are retried with exponential backoff from one minute, up to
+/// eight attempts, whenever due retries are run for the store, either on a schedule or
+/// through `RETRY_WEBHOOK_DELIVERIES`.
+pub struct MerchantNotifier {
+    config: Option<MerchantWebhookConfig>,
+    deliveries: Arc<dyn DeliveryStore>,
+    http_client: HttpClient,
+}
+
+impl MerchantNotifier {
+    pub fn new(config: Option<MerchantWebhookConfig>, deliveries: Arc<dyn DeliveryStore>, http_client: HttpClient) -> Self {
+        MerchantNotifier {
+            config,
+            deliveries,
+            http_client,
+        }
+    }
+
+    /// Logs a pending delivery of the record's current status to every subscribed endpoint,
+    /// then makes the first attempt at each before returning. Lambda freezes the environment
+    /// once the response is returned, so nothing is left running past the request; each
+    /// attempt is capped by a short timeout, and deliveries that fail stay pending for the
+    /// next retry run. Records without a status, or stores without subscriptions, are skipped.
+    pub async fn notify(&self, record: &TransactionRecord) -> Result<(), GatewayError> {
+        let (Some(config), Some(status)) = (&self.config, &record.status) else {
+            return Ok(());
+        };
+        let event_type = format!("{}.{}", record.object_type, status);
+        let endpoints: Vec<&MerchantEndpoint> = config.endpoints.iter()
+            .filter(|endpoint| event_type_matches(&endpoint.events, &event_type))
+            .collect();
+        if endpoints.is_empty() {
+            return Ok(());
+        }
+
+        let now = now_secs();
+        let event = GatewayEvent {
+            id: format!("evt_{}", Uuid::new_v4().simple()),
+            event_type: &event_type,
+            created: now,
+            store_id: &record.store_id,
+            data: record,
+        };
+        let payload = serde_json::to_value(&event)?;
+        let mut pending = Vec::new();
+        for endpoint in endpoints {
+            let delivery = WebhookDelivery {
+                delivery_id: format!("whd_{}", Uuid::new_v4().simple()),
+                store_id: record.store_id.clone(),
+                event_id: event.id.clone(),
+                event_type: event_type.clone(),
+                url: endpoint.url.clone(),
+                payload: payload.clone(),
+                status: DELIVERY_PENDING.to_string(),
+                attempts: 0,
+                last_status_code: None,
+                last_error: None,
+                next_attempt_at: Some(now),
+                created_at: now,
+                updated_at: now,
+            };
+            match self.deliveries.save_delivery(&delivery).await {
+                Ok(()) => pending.push(delivery),
+                Err(e) => tracing::error!(
+                    delivery_id = %delivery.delivery_id,
+                    url = %redact::url(&delivery.url),
+                    "Failed to log merchant webhook delivery: {}", e
+                ),
+            }
+        }
+        if pending.is_empty() {
+            return Ok(());
+        }
+
+        self.attempt_all(&mut pending).await;
+        Ok(())
+    }
+
+    /// Attempts every delivery of the store whose retry is due.
+    pub async fn retry_due(&self, store_id: &str, limit: usize) -> Result<Vec<WebhookDelivery>, GatewayError> {
+        let mut due = self.deliveries.due_deliveries(store_id, now_secs(), limit).await?;
+        self.attempt_all(&mut due).await;
+        Ok(due)
+    }
+
+    /// Sends a logged delivery again, whatever its status.
+    pub async fn replay(&self, store_id: &str, delivery_id: &str) -> Result<WebhookDelivery, GatewayError> {
+        let mut delivery = self.deliveries.get_delivery(store_id, delivery_id).await?
+            .ok_or_else(|| GatewayError::InvalidRequest(format!("Unknown webhook delivery: {}", delivery_id)))?;
+        self.attempt(&mut delivery).await?;
+        Ok(delivery)
+    }
+
+    /// Attempts each delivery in turn. A delivery whose outcome cannot be logged is reported
+    /// and skipped, rather than holding back the rest.
+    async fn attempt_all(&self, deliveries: &mut [WebhookDelivery]) {
+        for delivery in deliveries.iter_mut() {
+            if let Err(e) = self.attempt(delivery).await {
+                tracing::error!(delivery_id = %delivery.delivery_id, "Failed to log merchant webhook attempt: {}", e);
+            }
+        }
+    }
+
+    /// Makes one attempt, then logs the outcome along with the next retry if it failed.
+    async fn attempt(&self, delivery: &mut WebhookDelivery) -> Result<(), GatewayError> {
+        let config = self.config.as_ref()
+            .ok_or_else(|| GatewayError::InvalidRequest("Merchant webhooks are not configured for this store".to_string()))?;
+        let body = serde_json::to_string(&delivery.payload)?;
+        let timestamp = now_secs();
+        let signature = sign(&config.signing_secret, timestamp, &body)?;
+
+        let result = self.http_client.post(&delivery.url)
+            .timeout(DELIVERY_TIMEOUT)
+            .header("Content-Type", "application/json")
+            .header("X-Gateway-Signature", format!("t={},v1={}", timestamp, signature))
+            .header("X-Gateway-Event-Id", &delivery.event_id)
+            .header("X-Gateway-Delivery-Id", &delivery.delivery_id)
+            .body(body)
+            .send()
+            .await;
+
+        delivery.attempts += 1;
+        delivery.updated_at = now_secs();
+        match result {
+            Ok(response) if response.status().is_success() => {
+                delivery.status = DELIVERY_SUCCEEDED.to_string();
+                delivery.last_status_code = Some(response.status().as_u16() as i64);
+                delivery.last_error = None;
+                delivery.next_attempt_at = None;
+            }
+            Ok(response) => {
+                delivery.last_status_code = Some(response.status().as_u16() as i64);
+                delivery.last_error = Some(format!("Endpoint responded with {}", response.status()));
+                self.schedule_retry(delivery);
+            }
+            Err(e) => {
+                delivery.last_status_code = None;
+                delivery.last_error = Some(e.without_url().to_string());
+                self.schedule_retry(delivery);
+            }
+        }
+
+        tracing::info!(
+            delivery_id = %delivery.delivery_id,
+            url = %redact::url(&delivery.url),
+            attempts = delivery.attempts,
+            outcome = %delivery.status,
+            "Merchant webhook attempted"
+        );
+        metrics::count("MerchantWebhookDeliveries", &[("Outcome", &delivery.status)]);
+        self.deliveries.save_delivery(delivery).await
+    }
+
+    fn schedule_retry(&self, delivery: &mut WebhookDelivery) {
+        if delivery.attempts >= MAX_ATTEMPTS {
+            delivery.status = DELIVERY_FAILED.to_string();
+            delivery.next_attempt_at = None;
+        } else {
+            delivery.status = DELIVERY_PENDING.to_string();
+            delivery.next_attempt_at = Some(delivery.updated_at + (RETRY_BASE_DELAY_SECS << (delivery.attempts - 1)));
+        }
+    }
+}
+
+fn sign(secret: &str, timestamp: i64, body: &str) -> Result<String, GatewayError> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())
+        .map_err(|e| GatewayError::Unexpected(format!("Invalid signing secret: {}", e)))?;
+    mac.update(format!("{}.{}", timestamp, body).as_bytes());
+    Ok(hex::encode(mac.finalize().into_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ledger::InMemoryTransactionStore;
+
+    fn notifier(events: &[&str], deliveries: Arc<InMemoryTransactionStore>) -> MerchantNotifier {
+        let config = MerchantWebhookConfig {
+            signing_secret: "whsec_test".to_string(),
+            endpoints: vec![MerchantEndpoint {
+                // Nothing listens on the discard port, so every attempt fails fast.
+                url: "http://127.0.0.1:9/webhooks".to_string(),
+                events: events.iter().map(|event| event.to_string()).collect(),
+            }],
+        };
+        MerchantNotifier::new(Some(config), deliveries, HttpClient::new())
+    }
+
+    fn delivery(attempts: i64) -> WebhookDelivery {
+        WebhookDelivery {
+            delivery_id: "whd_1".to_string(),
+            store_id: "store-a".to_string(),
+            event_id: "evt_1".to_string(),
+            event_type: "charge.succeeded".to_string(),
+            url: "https://merchant.example.com/webhooks".to_string(),
+            payload: serde_json::json!({}),
+            status: DELIVERY_PENDING.to_string(),
+            attempts,
+            last_status_code: Some(500),
+            last_error: None,
+            next_attempt_at: None,
+            created_at: 1_000,
+            updated_at: 1_000,
+        }
+    }
+
+    fn record(status: &str) -> TransactionRecord {
+        TransactionRecord {
+            store_id: "store-a".to_string(),
+            object_id: "ch_1".to_string(),
+            object_type: "charge".to_string(),
+            request_type: "CHARGE".to_string(),
+            status: Some(status.to_string()),
+            amount: Some(1000),
+            currency: Some("usd".to_string()),
+            related_object_id: None,
+            event_type: None,
+            metadata: None,
+            recorded_at: 1_000,
+        }
+    }
+
+    #[test]
+    fn retries_back_off_exponentially() {
+        let notifier = notifier(&[], Arc::new(InMemoryTransactionStore::new()));
+        for (attempts, delay) in [(1, 60), (2, 120), (3, 240), (7, 3_840)] {
+            let mut delivery = delivery(attempts);
+            notifier.schedule_retry(&mut delivery);
+            assert_eq!(delivery.status, DELIVERY_PENDING);
+            assert_eq!(delivery.next_attempt_at, Some(1_000 + delay));
+        }
+    }
+
+    #[test]
+    fn retries_stop_at_the_maximum_attempts() {
+        let notifier = notifier(&[], Arc::new(InMemoryTransactionStore::new()));
+        let mut delivery = delivery(MAX_ATTEMPTS);
+        notifier.schedule_retry(&mut delivery);
+        assert_eq!(delivery.status, DELIVERY_FAILED);
+        assert_eq!(delivery.next_attempt_at, None);
+    }
+
+    #[test]
+    fn signs_the_timestamp_and_body() {
+        let signature = sign("whsec_test", 1_700_000_000, r#"{"id":"evt_1"}"#).unwrap();
+        assert_eq!(signature, "c89214b5b5da833daed6f0b8c5bb6bd58cea9022bd80ccc78230f3942d632925");
+        assert_ne!(signature, sign("whsec_other", 1_700_000_000, r#"{"id":"evt_1"}"#).unwrap());
+    }
+
+    #[tokio::test]
+    async fn notify_makes_the_first_attempt_before_returning() {
+        let deliveries = Arc::new(InMemoryTransactionStore::new());
+        notifier(&["charge.*"], deliveries.clone()).notify(&record("succeeded")).await.unwrap();
+
+        let logged = deliveries.list_deliveries("store-a", 10).await.unwrap();
+        assert_eq!(logged.len(), 1);
+        let delivery = &logged[0];
+        assert_eq!(delivery.event_type, "charge.succeeded");
+        assert_eq!(delivery.attempts, 1);
+        assert_eq!(delivery.status, DELIVERY_PENDING);
+        assert!(delivery.last_error.is_some());
+        assert_eq!(delivery.next_attempt_at, Some(delivery.updated_at + RETRY_BASE_DELAY_SECS));
+    }
+
+    #[tokio::test]
+    async fn retry_due_attempts_pending_deliveries_again() {
+        let deliveries = Arc::new(InMemoryTransactionStore::new());
+        let notifier = notifier(&["charge.*"], deliveries.clone());
+        let mut due = delivery(1);
+        due.url = "http://127.0.0.1:9/webhooks".to_string();
+        due.next_attempt_at = Some(now_secs() - 1);
+        deliveries.save_delivery(&due).await.unwrap();
+
+        let retried = notifier.retry_due("store-a", 10).await.unwrap();
+        assert_eq!(retried.len(), 1);
+        assert_eq!(retried[0].attempts, 2);
+        let saved = deliveries.get_delivery("store-a", "whd_1").await.unwrap().unwrap();
+        assert_eq!(saved.attempts, 2);
+        assert!(notifier.retry_due("store-a", 10).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn notify_skips_events_no_endpoint_subscribes_to() {
+        let deliveries = Arc::new(InMemoryTransactionStore::new());
+        notifier(&["refund.*"], deliveries.clone()).notify(&record("succeeded")).await.unwrap();
+        assert!(deliveries.list_deliveries("store-a", 10).await.unwrap().is_empty());
+    }
+}
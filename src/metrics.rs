@@ -0,0 +1,98 @@
+use std::future::Future;
+use std::sync::OnceLock;
+use std::time::{SystemTime, UNIX_EPOCH};
+use serde_json::{json, Map, Value};
+
+const DEFAULT_NAMESPACE: &str = "StripeGateway";
+
+tokio::task_local! {
+    static CONTEXT: MetricContext;
+}
+
+#[derive(Clone, Copy)]
+pub enum Unit {
+    Count,
+    Milliseconds,
+}
+
+impl Unit {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Unit::Count => "Count",
+            Unit::Milliseconds => "Milliseconds",
+        }
+    }
+}
+
+/// Dimensions shared by every metric emitted while handling one request.
+#[derive(Clone)]
+pub struct MetricContext {
+    pub store_id: String,
+    pub request_type: String,
+}
+
+/// Runs `future` with `context` attached, so metrics emitted anywhere below it
+/// (processors, the Stripe client, the secrets cache) are tagged with the store and request type.
+pub async fn scope<F: Future>(context: MetricContext, future: F) -> F::Output {
+    CONTEXT.scope(context, future).await
+}
+
+/// Writes a single CloudWatch Embedded Metric Format document to stdout.
+///
+/// The metric is published once per dimension set: on its own `extra` dimensions, with the
+/// request type added, and with both request type and store added.
+pub fn put(name: &str, value: f64, unit: Unit, extra: &[(&str, &str)]) {
+    let context = CONTEXT.try_with(|context| context.clone()).ok();
+
+    let mut document = Map::new();
+    let mut base: Vec<&str> = extra.iter().map(|(key, _)| *key).collect();
+    for (key, value) in extra {
+        document.insert(key.to_string(), Value::String(value.to_string()));
+    }
+
+    let mut dimension_sets = Vec::new();
+    if !base.is_empty() {
+        dimension_sets.push(base.clone());
+    }
+    if let Some(context) = &context {
+        document.insert("RequestType".to_string(), Value::String(context.request_type.clone()));
+        document.insert("StoreId".to_string(), Value::String(context.store_id.clone()));
+        base.insert(0, "RequestType");
+        dimension_sets.push(base.clone());
+        base.insert(1, "StoreId");
+        dimension_sets.push(base);
+    }
+    if dimension_sets.is_empty() {
+        dimension_sets.push(Vec::new());
+    }
+
+    document.insert(name.to_string(), json!(value));
+    document.insert("_aws".to_string(), json!({
+        "Timestamp": now_millis(),
+        "CloudWatchMetrics": [{
+            "Namespace": namespace(),
+            "Dimensions": dimension_sets,
+            "Metrics": [{ "Name": name, "Unit": unit.as_str() }],
+        }],
+    }));
+
+    println!("{}", Value::Object(document));
+}
+
+pub fn count(name: &str, extra: &[(&str, &str)]) {
+    put(name, 1.0, Unit::Count, extra);
+}
+
+fn namespace() -> &'static str {
+    static NAMESPACE: OnceLock<String> = OnceLock::new();
+    NAMESPACE.get_or_init(|| {
+        std::env::var("METRICS_NAMESPACE").unwrap_or_else(|_| DEFAULT_NAMESPACE.to_string())
+    })
+}
+
+fn now_millis() -> u128 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or(0)
+}
@@ -1,7 +1,9 @@
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::fmt;
+use crate::redact;
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize)]
 pub struct PaymentRequest {
     #[serde(rename = "storeId")]
     pub store_id: String,
@@ -22,30 +24,547 @@ pub struct PaymentRequest {
     pub session_id: Option<String>,
     #[serde(rename = "webhookEvent")]
     pub webhook_event: Option<HashMap<String, serde_json::Value>>,
+    #[serde(rename = "accountId")]
+    pub account_id: Option<String>,
+    #[serde(rename = "accountType")]
+    pub account_type: Option<String>,
+    pub email: Option<String>,
+    pub country: Option<String>,
+    #[serde(rename = "businessType")]
+    pub business_type: Option<String>,
+    pub capabilities: Option<Vec<String>>,
+    #[serde(rename = "refreshUrl")]
+    pub refresh_url: Option<String>,
+    #[serde(rename = "returnUrl")]
+    pub return_url: Option<String>,
+    #[serde(rename = "idempotencyKey")]
+    pub idempotency_key: Option<String>,
+    #[serde(rename = "objectId")]
+    pub object_id: Option<String>,
+    pub limit: Option<i64>,
+    #[serde(rename = "deliveryId")]
+    pub delivery_id: Option<String>,
+    #[serde(rename = "objectType")]
+    pub object_type: Option<String>,
+    pub expand: Option<Vec<String>>,
+    #[serde(rename = "createdGte")]
+    pub created_gte: Option<i64>,
+    #[serde(rename = "createdLte")]
+    pub created_lte: Option<i64>,
+    #[serde(rename = "customerId")]
+    pub customer_id: Option<String>,
+    pub status: Option<String>,
+    pub query: Option<String>,
+    /// List cursor: the ID of the last object of the previous page (`nextCursor`).
+    #[serde(rename = "startingAfter")]
+    pub starting_after: Option<String>,
+    /// Search cursor: the opaque `nextPage` token of the previous search page.
+    pub page: Option<String>,
+    #[serde(rename = "paymentIntentId")]
+    pub payment_intent_id: Option<String>,
+    #[serde(rename = "cancellationReason")]
+    pub cancellation_reason: Option<String>,
+    #[serde(rename = "disputeId")]
+    pub dispute_id: Option<String>,
+    /// Text evidence keyed by Stripe evidence field, e.g. `product_description`.
+    pub evidence: Option<HashMap<String, String>>,
+    #[serde(rename = "evidenceFiles")]
+    pub evidence_files: Option<Vec<EvidenceFile>>,
+    /// Submit the evidence to the card network now instead of only staging it.
+    pub submit: Option<bool>,
+    /// Attached to every Stripe object the request creates.
+    pub metadata: Option<HashMap<String, String>>,
+    #[serde(rename = "customerEmail")]
+    pub customer_email: Option<String>,
+    #[serde(rename = "clientReferenceId")]
+    pub client_reference_id: Option<String>,
+    #[serde(rename = "paymentMethodTypes")]
+    pub payment_method_types: Option<Vec<String>>,
+    /// `auto` or `required`.
+    #[serde(rename = "billingAddressCollection")]
+    pub billing_address_collection: Option<String>,
+    /// Countries to collect a shipping address for; shipping addresses are not collected without it.
+    #[serde(rename = "shippingCountries")]
+    pub shipping_countries: Option<Vec<String>>,
+    #[serde(rename = "shippingRates")]
+    pub shipping_rates: Option<Vec<String>>,
+    #[serde(rename = "phoneNumberCollection")]
+    pub phone_number_collection: Option<bool>,
+    #[serde(rename = "allowPromotionCodes")]
+    pub allow_promotion_codes: Option<bool>,
+    pub locale: Option<String>,
+    #[serde(rename = "customFields")]
+    pub custom_fields: Option<Vec<CheckoutCustomField>>,
+    #[serde(rename = "expiresAt")]
+    pub expires_at: Option<i64>,
+    #[serde(rename = "automaticTax")]
+    pub automatic_tax: Option<bool>,
+    /// Checkout UI: `hosted` (the default), `embedded` or `custom`.
+    #[serde(rename = "uiMode")]
+    pub ui_mode: Option<String>,
+    /// A saved payment method, charged or set up for the customer in `customerId`.
+    #[serde(rename = "paymentMethodId")]
+    pub payment_method_id: Option<String>,
+    /// Charge the saved payment method without the customer present.
+    #[serde(rename = "offSession")]
+    pub off_session: Option<bool>,
+    /// How a SETUP_INTENT's payment method will be used: `off_session` (the default) or `on_session`.
+    pub usage: Option<String>,
+    #[serde(rename = "productId")]
+    pub product_id: Option<String>,
+    /// A catalog price, sold by PAYMENT_LINK instead of an ad-hoc `amount`.
+    #[serde(rename = "priceId")]
+    pub price_id: Option<String>,
+    pub quantity: Option<i64>,
+    pub name: Option<String>,
+    pub active: Option<bool>,
+    pub recurring: Option<RecurringPrice>,
+    /// `graduated` or `volume`; required with `tiers`.
+    #[serde(rename = "tiersMode")]
+    pub tiers_mode: Option<String>,
+    pub tiers: Option<Vec<PriceTier>>,
+    /// Unit amounts in currencies other than `currency`, keyed by currency code.
+    #[serde(rename = "currencyOptions")]
+    pub currency_options: Option<HashMap<String, i64>>,
+    #[serde(rename = "lookupKey")]
+    pub lookup_key: Option<String>,
+    pub nickname: Option<String>,
+    #[serde(rename = "invoiceId")]
+    pub invoice_id: Option<String>,
+    /// Lines added to a new draft invoice.
+    #[serde(rename = "invoiceItems")]
+    pub invoice_items: Option<Vec<InvoiceItem>>,
+    /// `send_invoice` (the default) or `charge_automatically`.
+    #[serde(rename = "collectionMethod")]
+    pub collection_method: Option<String>,
+    /// Days the customer has to pay a `send_invoice` invoice (default 30).
+    #[serde(rename = "daysUntilDue")]
+    pub days_until_due: Option<i64>,
+    /// Overrides the store's Customer Portal configuration for one session.
+    #[serde(rename = "portalConfigurationId")]
+    pub portal_configuration_id: Option<String>,
+    /// A coupon to create, deactivate or filter by, or to apply to a CHARGE or PAYMENT_LINK.
+    #[serde(rename = "couponId")]
+    pub coupon_id: Option<String>,
+    /// The code customers enter, e.g. `SUMMER20`; applied like `couponId`.
+    #[serde(rename = "promotionCode")]
+    pub promotion_code: Option<String>,
+    #[serde(rename = "promotionCodeId")]
+    pub promotion_code_id: Option<String>,
+    #[serde(rename = "percentOff")]
+    pub percent_off: Option<f64>,
+    /// A fixed discount in the smallest unit of `currency`.
+    #[serde(rename = "amountOff")]
+    pub amount_off: Option<i64>,
+    /// How long a coupon applies to a subscription: `once`, `repeating` or `forever`.
+    pub duration: Option<String>,
+    #[serde(rename = "durationInMonths")]
+    pub duration_in_months: Option<i64>,
+    #[serde(rename = "maxRedemptions")]
+    pub max_redemptions: Option<i64>,
+    #[serde(rename = "redeemBy")]
+    pub redeem_by: Option<i64>,
+    #[serde(rename = "payoutId")]
+    pub payout_id: Option<String>,
+    /// Balance transaction type filter, e.g. `charge`, `refund` or `payout`.
+    #[serde(rename = "transactionType")]
+    pub transaction_type: Option<String>,
 }
 
-#[derive(Serialize, Debug)]
+/// An invoice line, either an ad-hoc `amount` in the request's currency or a catalog price.
+#[derive(Deserialize, Debug)]
+pub struct InvoiceItem {
+    pub description: Option<String>,
+    pub amount: Option<i64>,
+    #[serde(rename = "priceId")]
+    pub price_id: Option<String>,
+    pub quantity: Option<i64>,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct RecurringPrice {
+    /// `day`, `week`, `month` or `year`.
+    pub interval: String,
+    #[serde(rename = "intervalCount")]
+    pub interval_count: Option<i64>,
+    /// `licensed` (the default) or `metered`.
+    #[serde(rename = "usageType")]
+    pub usage_type: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct PriceTier {
+    /// Upper bound of the tier in units; omitted for the last, unbounded tier.
+    #[serde(rename = "upTo")]
+    pub up_to: Option<i64>,
+    #[serde(rename = "unitAmount")]
+    pub unit_amount: Option<i64>,
+    #[serde(rename = "flatAmount")]
+    pub flat_amount: Option<i64>,
+}
+
+/// An extra input shown on the Checkout page.
+#[derive(Deserialize, Debug)]
+pub struct CheckoutCustomField {
+    pub key: String,
+    pub label: String,
+    /// `text`, `numeric` or `dropdown`.
+    #[serde(rename = "type")]
+    pub field_type: String,
+    #[serde(default)]
+    pub optional: bool,
+    /// Choices for `dropdown` fields.
+    #[serde(default)]
+    pub options: Vec<CheckoutFieldOption>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct CheckoutFieldOption {
+    pub label: String,
+    pub value: String,
+}
+
+/// A file uploaded through the Files API and attached to a dispute evidence field.
+#[derive(Deserialize)]
+pub struct EvidenceFile {
+    /// Evidence field the file is attached to, e.g. `receipt` or `shipping_documentation`.
+    pub field: String,
+    #[serde(rename = "fileName")]
+    pub file_name: String,
+    #[serde(rename = "contentType")]
+    pub content_type: String,
+    /// Base64-encoded file contents.
+    pub content: String,
+}
+
+impl fmt::Debug for PaymentRequest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let webhook_event = self.webhook_event.as_ref().map(|event| {
+            format!("{{id: {:?}, type: {:?}}}", event.get("id"), event.get("type"))
+        });
+        f.debug_struct("PaymentRequest")
+            .field("store_id", &self.store_id)
+            .field("amount", &self.amount)
+            .field("currency", &self.currency)
+            .field("payment_token", &redact::mask_opt(&self.payment_token))
+            .field("description", &redact::text_opt(&self.description))
+            .field("request_type", &self.request_type)
+            .field("success_url", &redact::url_opt(&self.success_url))
+            .field("cancel_url", &redact::url_opt(&self.cancel_url))
+            .field("charge_id", &self.charge_id)
+            .field("session_id", &self.session_id)
+            .field("webhook_event", &webhook_event)
+            .field("account_id", &self.account_id)
+            .field("account_type", &self.account_type)
+            .field("email", &redact::mask_email_opt(&self.email))
+            .field("country", &self.country)
+            .field("business_type", &self.business_type)
+            .field("capabilities", &self.capabilities)
+            .field("refresh_url", &redact::url_opt(&self.refresh_url))
+            .field("return_url", &redact::url_opt(&self.return_url))
+            .field("idempotency_key", &self.idempotency_key)
+            .field("object_id", &self.object_id)
+            .field("delivery_id", &self.delivery_id)
+            .field("object_type", &self.object_type)
+            .field("expand", &self.expand)
+            .field("created_gte", &self.created_gte)
+            .field("created_lte", &self.created_lte)
+            .field("customer_id", &self.customer_id)
+            .field("status", &self.status)
+            .field("query", &redact::text_opt(&self.query))
+            .field("starting_after", &self.starting_after)
+            .field("page", &self.page)
+            .field("payment_intent_id", &self.payment_intent_id)
+            .field("cancellation_reason", &self.cancellation_reason)
+            .field("dispute_id", &self.dispute_id)
+            .field("evidence", &self.evidence.as_ref().map(|evidence| evidence.keys().collect::<Vec<_>>()))
+            .field("evidence_files", &self.evidence_files.as_ref().map(|files| {
+                files.iter().map(|file| file.field.as_str()).collect::<Vec<_>>()
+            }))
+            .field("submit", &self.submit)
+            .field("metadata", &self.metadata.as_ref().map(|metadata| metadata.keys().collect::<Vec<_>>()))
+            .field("customer_email", &redact::mask_email_opt(&self.customer_email))
+            .field("client_reference_id", &redact::text_opt(&self.client_reference_id))
+            .field("payment_method_types", &self.payment_method_types)
+            .field("billing_address_collection", &self.billing_address_collection)
+            .field("shipping_countries", &self.shipping_countries)
+            .field("shipping_rates", &self.shipping_rates)
+            .field("phone_number_collection", &self.phone_number_collection)
+            .field("allow_promotion_codes", &self.allow_promotion_codes)
+            .field("locale", &self.locale)
+            .field("custom_fields", &self.custom_fields)
+            .field("expires_at", &self.expires_at)
+            .field("automatic_tax", &self.automatic_tax)
+            .field("ui_mode", &self.ui_mode)
+            .field("payment_method_id", &self.payment_method_id)
+            .field("off_session", &self.off_session)
+            .field("usage", &self.usage)
+            .field("product_id", &self.product_id)
+            .field("price_id", &self.price_id)
+            .field("quantity", &self.quantity)
+            .field("name", &redact::text_opt(&self.name))
+            .field("active", &self.active)
+            .field("recurring", &self.recurring)
+            .field("tiers_mode", &self.tiers_mode)
+            .field("tiers", &self.tiers)
+            .field("currency_options", &self.currency_options)
+            .field("lookup_key", &self.lookup_key)
+            .field("nickname", &redact::text_opt(&self.nickname))
+            .field("invoice_id", &self.invoice_id)
+            .field("invoice_items", &self.invoice_items.as_ref().map(Vec::len))
+            .field("collection_method", &self.collection_method)
+            .field("days_until_due", &self.days_until_due)
+            .field("portal_configuration_id", &self.portal_configuration_id)
+            .field("coupon_id", &self.coupon_id)
+            .field("promotion_code", &self.promotion_code)
+            .field("promotion_code_id", &self.promotion_code_id)
+            .field("percent_off", &self.percent_off)
+            .field("amount_off", &self.amount_off)
+            .field("duration", &self.duration)
+            .field("duration_in_months", &self.duration_in_months)
+            .field("max_redemptions", &self.max_redemptions)
+            .field("redeem_by", &self.redeem_by)
+            .field("payout_id", &self.payout_id)
+            .field("transaction_type", &self.transaction_type)
+            .field("limit", &self.limit)
+            .finish()
+    }
+}
+
+#[derive(Serialize)]
 pub struct ChargeResponse {
     pub status: String,
     pub message: Option<String>,
     #[serde(rename = "chargeId")]
     pub charge_id: Option<String>,
+    /// Set for off-session charges, which are made through a payment intent.
+    #[serde(rename = "paymentIntentId")]
+    pub payment_intent_id: Option<String>,
+    /// Set when an off-session charge needs the customer to authenticate it on-session.
+    #[serde(rename = "clientSecret")]
+    pub client_secret: Option<String>,
     pub amount: Option<i64>,
     pub currency: Option<String>,
+    #[serde(rename = "paymentStatus")]
+    pub payment_status: Option<String>,
+    pub discount: Option<AppliedDiscount>,
+    pub metadata: Option<HashMap<String, String>>,
     #[serde(rename = "statusCode")]
     pub status_code: i32,
 }
 
+/// A coupon taken off a CHARGE's amount before it was charged.
 #[derive(Serialize, Debug)]
+pub struct AppliedDiscount {
+    #[serde(rename = "couponId")]
+    pub coupon_id: Option<String>,
+    #[serde(rename = "promotionCodeId")]
+    pub promotion_code_id: Option<String>,
+    #[serde(rename = "originalAmount")]
+    pub original_amount: i64,
+    #[serde(rename = "amountDiscounted")]
+    pub amount_discounted: i64,
+}
+
+impl fmt::Debug for ChargeResponse {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ChargeResponse")
+            .field("status", &self.status)
+            .field("message", &self.message)
+            .field("charge_id", &self.charge_id)
+            .field("payment_intent_id", &self.payment_intent_id)
+            .field("client_secret", &self.client_secret.as_ref().map(|_| "****"))
+            .field("amount", &self.amount)
+            .field("currency", &self.currency)
+            .field("payment_status", &self.payment_status)
+            .field("discount", &self.discount)
+            .field("metadata", &self.metadata)
+            .field("status_code", &self.status_code)
+            .finish()
+    }
+}
+
+/// A setup intent saving a payment method to a customer for later charges.
+#[derive(Serialize)]
+pub struct SetupIntentResponse {
+    pub status: String,
+    pub message: Option<String>,
+    #[serde(rename = "setupIntentId")]
+    pub setup_intent_id: Option<String>,
+    #[serde(rename = "clientSecret")]
+    pub client_secret: Option<String>,
+    #[serde(rename = "setupStatus")]
+    pub setup_status: Option<String>,
+    #[serde(rename = "customerId")]
+    pub customer_id: Option<String>,
+    #[serde(rename = "paymentMethodId")]
+    pub payment_method_id: Option<String>,
+    pub usage: Option<String>,
+    pub metadata: Option<HashMap<String, String>>,
+    #[serde(rename = "statusCode")]
+    pub status_code: i32,
+}
+
+impl fmt::Debug for SetupIntentResponse {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SetupIntentResponse")
+            .field("status", &self.status)
+            .field("message", &self.message)
+            .field("setup_intent_id", &self.setup_intent_id)
+            .field("client_secret", &self.client_secret.as_ref().map(|_| "****"))
+            .field("setup_status", &self.setup_status)
+            .field("customer_id", &self.customer_id)
+            .field("payment_method_id", &self.payment_method_id)
+            .field("usage", &self.usage)
+            .field("metadata", &self.metadata)
+            .field("status_code", &self.status_code)
+            .finish()
+    }
+}
+
+/// Result of an AUTHORIZE, CAPTURE or VOID on a manually captured payment intent.
+#[derive(Serialize, Debug)]
+pub struct AuthorizationResponse {
+    pub status: String,
+    pub message: Option<String>,
+    #[serde(rename = "paymentIntentId")]
+    pub payment_intent_id: Option<String>,
+    #[serde(rename = "chargeId")]
+    pub charge_id: Option<String>,
+    pub amount: Option<i64>,
+    #[serde(rename = "amountCapturable")]
+    pub amount_capturable: Option<i64>,
+    #[serde(rename = "amountReceived")]
+    pub amount_received: Option<i64>,
+    pub currency: Option<String>,
+    #[serde(rename = "paymentStatus")]
+    pub payment_status: Option<String>,
+    /// When the authorization lapses if not captured (Unix seconds).
+    #[serde(rename = "captureBefore")]
+    pub capture_before: Option<i64>,
+    pub metadata: Option<HashMap<String, String>>,
+    #[serde(rename = "statusCode")]
+    pub status_code: i32,
+}
+
+/// A payment intent for the browser to confirm with Stripe Elements.
+#[derive(Serialize)]
+pub struct PaymentIntentResponse {
+    pub status: String,
+    pub message: Option<String>,
+    #[serde(rename = "paymentIntentId")]
+    pub payment_intent_id: Option<String>,
+    #[serde(rename = "clientSecret")]
+    pub client_secret: Option<String>,
+    pub amount: Option<i64>,
+    pub currency: Option<String>,
+    #[serde(rename = "paymentStatus")]
+    pub payment_status: Option<String>,
+    pub metadata: Option<HashMap<String, String>>,
+    #[serde(rename = "statusCode")]
+    pub status_code: i32,
+}
+
+impl fmt::Debug for PaymentIntentResponse {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // The client secret lets whoever holds it confirm the payment.
+        f.debug_struct("PaymentIntentResponse")
+            .field("status", &self.status)
+            .field("message", &self.message)
+            .field("payment_intent_id", &self.payment_intent_id)
+            .field("client_secret", &self.client_secret.as_ref().map(|_| "****"))
+            .field("amount", &self.amount)
+            .field("currency", &self.currency)
+            .field("payment_status", &self.payment_status)
+            .field("metadata", &self.metadata)
+            .field("status_code", &self.status_code)
+            .finish()
+    }
+}
+
+#[derive(Serialize)]
 pub struct PaymentLinkResponse {
     pub status: String,
     pub message: Option<String>,
     #[serde(rename = "paymentLink")]
     pub payment_link: Option<String>,
+    #[serde(rename = "sessionId")]
+    pub session_id: Option<String>,
+    #[serde(rename = "sessionStatus")]
+    pub session_status: Option<String>,
+    /// What the customer pays, after discounts, in the smallest currency unit.
+    #[serde(rename = "amountTotal")]
+    pub amount_total: Option<i64>,
+    pub currency: Option<String>,
+    /// Set instead of `paymentLink` for embedded and custom UI sessions.
+    #[serde(rename = "clientSecret")]
+    pub client_secret: Option<String>,
+    pub metadata: Option<HashMap<String, String>>,
     #[serde(rename = "statusCode")]
     pub status_code: i32,
 }
 
+impl fmt::Debug for PaymentLinkResponse {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PaymentLinkResponse")
+            .field("status", &self.status)
+            .field("message", &self.message)
+            .field("payment_link", &redact::url_opt(&self.payment_link))
+            .field("session_id", &self.session_id)
+            .field("session_status", &self.session_status)
+            .field("amount_total", &self.amount_total)
+            .field("currency", &self.currency)
+            .field("client_secret", &self.client_secret.as_ref().map(|_| "****"))
+            .field("metadata", &self.metadata)
+            .field("status_code", &self.status_code)
+            .finish()
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct SessionLineItemsResponse {
+    pub status: String,
+    pub message: Option<String>,
+    #[serde(rename = "sessionId")]
+    pub session_id: Option<String>,
+    #[serde(rename = "sessionStatus")]
+    pub session_status: Option<String>,
+    #[serde(rename = "paymentIntentId")]
+    pub payment_intent_id: Option<String>,
+    #[serde(rename = "customerId")]
+    pub customer_id: Option<String>,
+    #[serde(rename = "lineItems")]
+    pub line_items: Vec<LineItem>,
+    #[serde(rename = "hasMore")]
+    pub has_more: bool,
+    #[serde(rename = "nextCursor")]
+    pub next_cursor: Option<String>,
+    #[serde(rename = "statusCode")]
+    pub status_code: i32,
+}
+
+#[derive(Serialize, Debug)]
+pub struct LineItem {
+    pub id: Option<String>,
+    pub description: Option<String>,
+    pub quantity: Option<i64>,
+    #[serde(rename = "priceId")]
+    pub price_id: Option<String>,
+    #[serde(rename = "productId")]
+    pub product_id: Option<String>,
+    #[serde(rename = "unitAmount")]
+    pub unit_amount: Option<i64>,
+    pub currency: Option<String>,
+    #[serde(rename = "amountSubtotal")]
+    pub amount_subtotal: Option<i64>,
+    #[serde(rename = "amountDiscount")]
+    pub amount_discount: Option<i64>,
+    #[serde(rename = "amountTax")]
+    pub amount_tax: Option<i64>,
+    #[serde(rename = "amountTotal")]
+    pub amount_total: Option<i64>,
+}
+
 #[derive(Serialize, Debug)]
 pub struct RefundResponse {
     pub status: String,
@@ -54,6 +573,9 @@ pub struct RefundResponse {
     pub refund_id: Option<String>,
     pub amount: Option<i64>,
     pub currency: Option<String>,
+    #[serde(rename = "refundStatus")]
+    pub refund_status: Option<String>,
+    pub metadata: Option<HashMap<String, String>>,
     #[serde(rename = "statusCode")]
     pub status_code: i32,
 }
@@ -64,20 +586,663 @@ pub struct PaymentStatusResponse {
     pub message: Option<String>,
     #[serde(rename = "paymentId")]
     pub payment_id: Option<String>,
+    #[serde(rename = "objectType")]
+    pub object_type: Option<String>,
     #[serde(rename = "paymentStatus")]
     pub payment_status: Option<String>,
     pub amount: Option<i64>,
     pub currency: Option<String>,
+    #[serde(rename = "customerId")]
+    pub customer_id: Option<String>,
+    pub created: Option<i64>,
+    #[serde(rename = "failureCode")]
+    pub failure_code: Option<String>,
+    #[serde(rename = "failureMessage")]
+    pub failure_message: Option<String>,
+    /// The charge itself, or the one behind a payment intent, session, refund or dispute.
+    pub charge: Option<ChargeDetails>,
+    /// The objects requested with `expand`, keyed by their expand path.
+    pub expanded: Option<serde_json::Map<String, serde_json::Value>>,
+    pub metadata: Option<HashMap<String, String>>,
     #[serde(rename = "statusCode")]
     pub status_code: i32,
 }
 
+#[derive(Serialize)]
+pub struct ChargeDetails {
+    #[serde(rename = "chargeId")]
+    pub charge_id: Option<String>,
+    pub status: Option<String>,
+    pub amount: Option<i64>,
+    pub captured: Option<bool>,
+    pub refunded: Option<bool>,
+    #[serde(rename = "amountRefunded")]
+    pub amount_refunded: Option<i64>,
+    #[serde(rename = "failureCode")]
+    pub failure_code: Option<String>,
+    #[serde(rename = "failureMessage")]
+    pub failure_message: Option<String>,
+    #[serde(rename = "receiptUrl")]
+    pub receipt_url: Option<String>,
+    #[serde(rename = "paymentMethod")]
+    pub payment_method: Option<PaymentMethodDetails>,
+}
+
+impl fmt::Debug for ChargeDetails {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Receipt URLs are unauthenticated links to the customer's receipt.
+        f.debug_struct("ChargeDetails")
+            .field("charge_id", &self.charge_id)
+            .field("status", &self.status)
+            .field("amount", &self.amount)
+            .field("captured", &self.captured)
+            .field("refunded", &self.refunded)
+            .field("amount_refunded", &self.amount_refunded)
+            .field("failure_code", &self.failure_code)
+            .field("failure_message", &self.failure_message)
+            .field("receipt_url", &self.receipt_url.as_ref().map(|_| "****"))
+            .field("payment_method", &self.payment_method)
+            .finish()
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct PaymentMethodDetails {
+    #[serde(rename = "type")]
+    pub method_type: Option<String>,
+    pub brand: Option<String>,
+    pub last4: Option<String>,
+    #[serde(rename = "expMonth")]
+    pub exp_month: Option<i64>,
+    #[serde(rename = "expYear")]
+    pub exp_year: Option<i64>,
+    pub country: Option<String>,
+    pub wallet: Option<String>,
+}
+
 #[derive(Serialize, Debug)]
 pub struct WebhookResponse {
     pub status: String,
     pub message: Option<String>,
     #[serde(rename = "eventId")]
     pub event_id: Option<String>,
+    #[serde(rename = "eventType")]
+    pub event_type: Option<String>,
+    pub duplicate: bool,
+    pub stale: bool,
+    /// Set for `charge.dispute.*` events.
+    pub dispute: Option<DisputeDetails>,
+    /// Set for `invoice.*` events.
+    pub invoice: Option<InvoiceDetails>,
+    #[serde(rename = "statusCode")]
+    pub status_code: i32,
+}
+
+#[derive(Serialize, Debug)]
+pub struct DisputeDetails {
+    #[serde(rename = "disputeId")]
+    pub dispute_id: Option<String>,
+    #[serde(rename = "chargeId")]
+    pub charge_id: Option<String>,
+    #[serde(rename = "paymentIntentId")]
+    pub payment_intent_id: Option<String>,
+    pub amount: Option<i64>,
+    pub currency: Option<String>,
+    pub reason: Option<String>,
+    #[serde(rename = "disputeStatus")]
+    pub dispute_status: Option<String>,
+    #[serde(rename = "evidenceDueBy")]
+    pub evidence_due_by: Option<i64>,
+    #[serde(rename = "hasEvidence")]
+    pub has_evidence: Option<bool>,
+    #[serde(rename = "pastDue")]
+    pub past_due: Option<bool>,
+    #[serde(rename = "submissionCount")]
+    pub submission_count: Option<i64>,
+    #[serde(rename = "isChargeRefundable")]
+    pub is_charge_refundable: Option<bool>,
+    pub created: Option<i64>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct DisputeResponse {
+    pub status: String,
+    pub message: Option<String>,
+    pub disputes: Vec<DisputeDetails>,
+    #[serde(rename = "hasMore")]
+    pub has_more: bool,
+    #[serde(rename = "nextCursor")]
+    pub next_cursor: Option<String>,
+    #[serde(rename = "statusCode")]
+    pub status_code: i32,
+}
+
+#[derive(Serialize)]
+pub struct InvoiceDetails {
+    #[serde(rename = "invoiceId")]
+    pub invoice_id: Option<String>,
+    #[serde(rename = "customerId")]
+    pub customer_id: Option<String>,
+    /// `draft`, `open`, `paid`, `uncollectible` or `void`.
+    #[serde(rename = "invoiceStatus")]
+    pub invoice_status: Option<String>,
+    pub number: Option<String>,
+    pub currency: Option<String>,
+    pub total: Option<i64>,
+    #[serde(rename = "amountDue")]
+    pub amount_due: Option<i64>,
+    #[serde(rename = "amountPaid")]
+    pub amount_paid: Option<i64>,
+    #[serde(rename = "amountRemaining")]
+    pub amount_remaining: Option<i64>,
+    #[serde(rename = "collectionMethod")]
+    pub collection_method: Option<String>,
+    #[serde(rename = "dueDate")]
+    pub due_date: Option<i64>,
+    /// Set once the invoice is finalized.
+    #[serde(rename = "hostedInvoiceUrl")]
+    pub hosted_invoice_url: Option<String>,
+    #[serde(rename = "invoicePdf")]
+    pub invoice_pdf: Option<String>,
+    pub lines: Vec<InvoiceLine>,
+    pub created: Option<i64>,
+    pub metadata: Option<HashMap<String, String>>,
+}
+
+impl fmt::Debug for InvoiceDetails {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Hosted invoice and PDF links are unauthenticated, and the hosted page takes payment.
+        f.debug_struct("InvoiceDetails")
+            .field("invoice_id", &self.invoice_id)
+            .field("customer_id", &self.customer_id)
+            .field("invoice_status", &self.invoice_status)
+            .field("number", &self.number)
+            .field("currency", &self.currency)
+            .field("total", &self.total)
+            .field("amount_due", &self.amount_due)
+            .field("amount_paid", &self.amount_paid)
+            .field("amount_remaining", &self.amount_remaining)
+            .field("collection_method", &self.collection_method)
+            .field("due_date", &self.due_date)
+            .field("hosted_invoice_url", &self.hosted_invoice_url.as_ref().map(|_| "****"))
+            .field("invoice_pdf", &self.invoice_pdf.as_ref().map(|_| "****"))
+            .field("lines", &self.lines)
+            .field("created", &self.created)
+            .field("metadata", &self.metadata)
+            .finish()
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct InvoiceLine {
+    pub id: Option<String>,
+    pub description: Option<String>,
+    pub amount: Option<i64>,
+    pub currency: Option<String>,
+    pub quantity: Option<i64>,
+    #[serde(rename = "priceId")]
+    pub price_id: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct InvoiceResponse {
+    pub status: String,
+    pub message: Option<String>,
+    pub invoice: InvoiceDetails,
+    #[serde(rename = "statusCode")]
+    pub status_code: i32,
+}
+
+#[derive(Serialize)]
+pub struct BillingPortalResponse {
+    pub status: String,
+    pub message: Option<String>,
+    #[serde(rename = "portalSessionId")]
+    pub portal_session_id: Option<String>,
+    /// Short-lived link that signs the customer into the portal.
+    #[serde(rename = "portalUrl")]
+    pub portal_url: Option<String>,
+    #[serde(rename = "customerId")]
+    pub customer_id: Option<String>,
+    #[serde(rename = "configurationId")]
+    pub configuration_id: Option<String>,
+    #[serde(rename = "returnUrl")]
+    pub return_url: Option<String>,
+    #[serde(rename = "statusCode")]
+    pub status_code: i32,
+}
+
+impl fmt::Debug for BillingPortalResponse {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Whoever opens the portal URL is signed in as the customer.
+        f.debug_struct("BillingPortalResponse")
+            .field("status", &self.status)
+            .field("message", &self.message)
+            .field("portal_session_id", &self.portal_session_id)
+            .field("portal_url", &self.portal_url.as_ref().map(|_| "****"))
+            .field("customer_id", &self.customer_id)
+            .field("configuration_id", &self.configuration_id)
+            .field("return_url", &redact::url_opt(&self.return_url))
+            .field("status_code", &self.status_code)
+            .finish()
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct BalanceAmount {
+    pub amount: Option<i64>,
+    pub currency: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct BalanceResponse {
+    pub status: String,
+    pub message: Option<String>,
+    /// The connected account the balance belongs to, if not the store's own.
+    #[serde(rename = "accountId")]
+    pub account_id: Option<String>,
+    pub available: Vec<BalanceAmount>,
+    pub pending: Vec<BalanceAmount>,
+    #[serde(rename = "instantAvailable")]
+    pub instant_available: Vec<BalanceAmount>,
+    pub livemode: Option<bool>,
+    #[serde(rename = "statusCode")]
+    pub status_code: i32,
+}
+
+#[derive(Serialize, Debug)]
+pub struct BalanceTransactionDetails {
+    #[serde(rename = "balanceTransactionId")]
+    pub balance_transaction_id: Option<String>,
+    #[serde(rename = "type")]
+    pub transaction_type: Option<String>,
+    #[serde(rename = "reportingCategory")]
+    pub reporting_category: Option<String>,
+    pub amount: Option<i64>,
+    pub fee: Option<i64>,
+    pub net: Option<i64>,
+    pub currency: Option<String>,
+    /// `available` or `pending`.
+    #[serde(rename = "transactionStatus")]
+    pub transaction_status: Option<String>,
+    #[serde(rename = "availableOn")]
+    pub available_on: Option<i64>,
+    /// The charge, refund, payout, ... that moved the funds.
+    #[serde(rename = "sourceId")]
+    pub source_id: Option<String>,
+    pub description: Option<String>,
+    #[serde(rename = "feeDetails")]
+    pub fee_details: Vec<FeeDetail>,
+    pub created: Option<i64>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct FeeDetail {
+    /// `stripe_fee`, `application_fee` or `tax`.
+    #[serde(rename = "type")]
+    pub fee_type: Option<String>,
+    pub amount: Option<i64>,
+    pub currency: Option<String>,
+    pub description: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct BalanceTransactionResponse {
+    pub status: String,
+    pub message: Option<String>,
+    pub transactions: Vec<BalanceTransactionDetails>,
+    #[serde(rename = "hasMore")]
+    pub has_more: bool,
+    #[serde(rename = "nextCursor")]
+    pub next_cursor: Option<String>,
+    #[serde(rename = "statusCode")]
+    pub status_code: i32,
+}
+
+#[derive(Serialize, Debug)]
+pub struct PayoutDetails {
+    #[serde(rename = "payoutId")]
+    pub payout_id: Option<String>,
+    pub amount: Option<i64>,
+    pub currency: Option<String>,
+    /// `paid`, `pending`, `in_transit`, `canceled` or `failed`.
+    #[serde(rename = "payoutStatus")]
+    pub payout_status: Option<String>,
+    #[serde(rename = "arrivalDate")]
+    pub arrival_date: Option<i64>,
+    /// `standard` or `instant`.
+    pub method: Option<String>,
+    /// Automatic payouts settle a batch of balance transactions; manual ones do not.
+    pub automatic: Option<bool>,
+    #[serde(rename = "destinationId")]
+    pub destination_id: Option<String>,
+    #[serde(rename = "balanceTransactionId")]
+    pub balance_transaction_id: Option<String>,
+    #[serde(rename = "failureCode")]
+    pub failure_code: Option<String>,
+    #[serde(rename = "failureMessage")]
+    pub failure_message: Option<String>,
+    pub description: Option<String>,
+    pub created: Option<i64>,
+    pub metadata: Option<HashMap<String, String>>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct PayoutResponse {
+    pub status: String,
+    pub message: Option<String>,
+    pub payouts: Vec<PayoutDetails>,
+    /// For GET_PAYOUT, the first page of the balance transactions the payout settled; the
+    /// cursor continues them through LIST_BALANCE_TRANSACTIONS with the same `payoutId`.
+    pub transactions: Option<Vec<BalanceTransactionDetails>>,
+    #[serde(rename = "hasMore")]
+    pub has_more: bool,
+    #[serde(rename = "nextCursor")]
+    pub next_cursor: Option<String>,
+    #[serde(rename = "statusCode")]
+    pub status_code: i32,
+}
+
+#[derive(Serialize, Debug)]
+pub struct CouponDetails {
+    #[serde(rename = "couponId")]
+    pub coupon_id: Option<String>,
+    pub name: Option<String>,
+    #[serde(rename = "percentOff")]
+    pub percent_off: Option<f64>,
+    #[serde(rename = "amountOff")]
+    pub amount_off: Option<i64>,
+    pub currency: Option<String>,
+    pub duration: Option<String>,
+    #[serde(rename = "durationInMonths")]
+    pub duration_in_months: Option<i64>,
+    #[serde(rename = "maxRedemptions")]
+    pub max_redemptions: Option<i64>,
+    #[serde(rename = "timesRedeemed")]
+    pub times_redeemed: Option<i64>,
+    #[serde(rename = "redeemBy")]
+    pub redeem_by: Option<i64>,
+    /// False once the coupon is deactivated, expired or fully redeemed.
+    pub valid: Option<bool>,
+    pub created: Option<i64>,
+    pub metadata: Option<HashMap<String, String>>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct CouponResponse {
+    pub status: String,
+    pub message: Option<String>,
+    pub coupons: Vec<CouponDetails>,
+    #[serde(rename = "hasMore")]
+    pub has_more: bool,
+    #[serde(rename = "nextCursor")]
+    pub next_cursor: Option<String>,
+    #[serde(rename = "statusCode")]
+    pub status_code: i32,
+}
+
+#[derive(Serialize, Debug)]
+pub struct PromotionCodeDetails {
+    #[serde(rename = "promotionCodeId")]
+    pub promotion_code_id: Option<String>,
+    pub code: Option<String>,
+    #[serde(rename = "couponId")]
+    pub coupon_id: Option<String>,
+    pub active: Option<bool>,
+    /// Set when only this customer may redeem the code.
+    #[serde(rename = "customerId")]
+    pub customer_id: Option<String>,
+    #[serde(rename = "maxRedemptions")]
+    pub max_redemptions: Option<i64>,
+    #[serde(rename = "timesRedeemed")]
+    pub times_redeemed: Option<i64>,
+    #[serde(rename = "expiresAt")]
+    pub expires_at: Option<i64>,
+    pub created: Option<i64>,
+    pub metadata: Option<HashMap<String, String>>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct PromotionCodeResponse {
+    pub status: String,
+    pub message: Option<String>,
+    #[serde(rename = "promotionCodes")]
+    pub promotion_codes: Vec<PromotionCodeDetails>,
+    #[serde(rename = "hasMore")]
+    pub has_more: bool,
+    #[serde(rename = "nextCursor")]
+    pub next_cursor: Option<String>,
+    #[serde(rename = "statusCode")]
+    pub status_code: i32,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ProductDetails {
+    #[serde(rename = "productId")]
+    pub product_id: Option<String>,
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub active: Option<bool>,
+    #[serde(rename = "defaultPriceId")]
+    pub default_price_id: Option<String>,
+    pub created: Option<i64>,
+    pub updated: Option<i64>,
+    pub metadata: Option<HashMap<String, String>>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ProductResponse {
+    pub status: String,
+    pub message: Option<String>,
+    pub products: Vec<ProductDetails>,
+    #[serde(rename = "hasMore")]
+    pub has_more: bool,
+    #[serde(rename = "nextCursor")]
+    pub next_cursor: Option<String>,
+    #[serde(rename = "statusCode")]
+    pub status_code: i32,
+}
+
+#[derive(Serialize, Debug)]
+pub struct PriceDetails {
+    #[serde(rename = "priceId")]
+    pub price_id: Option<String>,
+    #[serde(rename = "productId")]
+    pub product_id: Option<String>,
+    pub active: Option<bool>,
+    /// `one_time` or `recurring`.
+    #[serde(rename = "type")]
+    pub price_type: Option<String>,
+    pub currency: Option<String>,
+    #[serde(rename = "unitAmount")]
+    pub unit_amount: Option<i64>,
+    pub recurring: Option<RecurringPrice>,
+    /// `per_unit` or `tiered`.
+    #[serde(rename = "billingScheme")]
+    pub billing_scheme: Option<String>,
+    #[serde(rename = "tiersMode")]
+    pub tiers_mode: Option<String>,
+    pub tiers: Option<Vec<PriceTier>>,
+    #[serde(rename = "currencyOptions")]
+    pub currency_options: Option<HashMap<String, i64>>,
+    #[serde(rename = "lookupKey")]
+    pub lookup_key: Option<String>,
+    pub nickname: Option<String>,
+    pub created: Option<i64>,
+    pub metadata: Option<HashMap<String, String>>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct PriceResponse {
+    pub status: String,
+    pub message: Option<String>,
+    pub prices: Vec<PriceDetails>,
+    #[serde(rename = "hasMore")]
+    pub has_more: bool,
+    #[serde(rename = "nextCursor")]
+    pub next_cursor: Option<String>,
+    #[serde(rename = "statusCode")]
+    pub status_code: i32,
+}
+
+#[derive(Serialize, Debug)]
+pub struct AccountRequirements {
+    #[serde(rename = "currentlyDue")]
+    pub currently_due: Vec<String>,
+    #[serde(rename = "eventuallyDue")]
+    pub eventually_due: Vec<String>,
+    #[serde(rename = "pastDue")]
+    pub past_due: Vec<String>,
+    #[serde(rename = "disabledReason")]
+    pub disabled_reason: Option<String>,
+    #[serde(rename = "currentDeadline")]
+    pub current_deadline: Option<i64>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct AccountResponse {
+    pub status: String,
+    pub message: Option<String>,
+    #[serde(rename = "accountId")]
+    pub account_id: Option<String>,
+    #[serde(rename = "accountType")]
+    pub account_type: Option<String>,
+    #[serde(rename = "chargesEnabled")]
+    pub charges_enabled: Option<bool>,
+    #[serde(rename = "payoutsEnabled")]
+    pub payouts_enabled: Option<bool>,
+    #[serde(rename = "detailsSubmitted")]
+    pub details_submitted: Option<bool>,
+    pub capabilities: Option<HashMap<String, String>>,
+    pub requirements: Option<AccountRequirements>,
+    pub metadata: Option<HashMap<String, String>>,
+    #[serde(rename = "statusCode")]
+    pub status_code: i32,
+}
+
+#[derive(Serialize)]
+pub struct AccountLinkResponse {
+    pub status: String,
+    pub message: Option<String>,
+    #[serde(rename = "accountId")]
+    pub account_id: Option<String>,
+    pub url: Option<String>,
+    #[serde(rename = "expiresAt")]
+    pub expires_at: Option<i64>,
+    #[serde(rename = "statusCode")]
+    pub status_code: i32,
+}
+
+impl fmt::Debug for AccountLinkResponse {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Account and login links grant access to the connected account until they expire.
+        f.debug_struct("AccountLinkResponse")
+            .field("status", &self.status)
+            .field("message", &self.message)
+            .field("account_id", &self.account_id)
+            .field("url", &self.url.as_ref().map(|_| "****"))
+            .field("expires_at", &self.expires_at)
+            .field("status_code", &self.status_code)
+            .finish()
+    }
+}
+
+/// One page of Stripe objects. While `hasMore` is true, pass `nextCursor` back as
+/// `startingAfter` for lists, or `nextPage` back as `page` for searches.
+#[derive(Serialize, Debug)]
+pub struct ListResponse {
+    pub status: String,
+    pub message: Option<String>,
+    #[serde(rename = "objectType")]
+    pub object_type: String,
+    pub items: Vec<ListItem>,
+    #[serde(rename = "hasMore")]
+    pub has_more: bool,
+    #[serde(rename = "nextCursor")]
+    pub next_cursor: Option<String>,
+    #[serde(rename = "nextPage")]
+    pub next_page: Option<String>,
+    #[serde(rename = "statusCode")]
+    pub status_code: i32,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ListItem {
+    pub id: Option<String>,
+    pub status: Option<String>,
+    pub amount: Option<i64>,
+    pub currency: Option<String>,
+    #[serde(rename = "customerId")]
+    pub customer_id: Option<String>,
+    pub created: Option<i64>,
+    pub metadata: Option<HashMap<String, String>>,
+}
+
+/// One gateway operation as recorded in the transaction ledger, keyed by store and Stripe object ID.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TransactionRecord {
+    #[serde(rename = "storeId")]
+    pub store_id: String,
+    #[serde(rename = "objectId")]
+    pub object_id: String,
+    #[serde(rename = "objectType")]
+    pub object_type: String,
+    #[serde(rename = "requestType")]
+    pub request_type: String,
+    pub status: Option<String>,
+    pub amount: Option<i64>,
+    pub currency: Option<String>,
+    #[serde(rename = "relatedObjectId")]
+    pub related_object_id: Option<String>,
+    #[serde(rename = "eventType")]
+    pub event_type: Option<String>,
+    pub metadata: Option<HashMap<String, String>>,
+    #[serde(rename = "recordedAt")]
+    pub recorded_at: i64,
+}
+
+#[derive(Serialize, Debug)]
+pub struct TransactionHistoryResponse {
+    pub status: String,
+    pub message: Option<String>,
+    pub transactions: Vec<TransactionRecord>,
+    #[serde(rename = "statusCode")]
+    pub status_code: i32,
+}
+
+/// One outbound webhook sent to a merchant endpoint, with its retry schedule.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct WebhookDelivery {
+    #[serde(rename = "deliveryId")]
+    pub delivery_id: String,
+    #[serde(rename = "storeId")]
+    pub store_id: String,
+    #[serde(rename = "eventId")]
+    pub event_id: String,
+    #[serde(rename = "eventType")]
+    pub event_type: String,
+    pub url: String,
+    pub payload: serde_json::Value,
+    /// `pending`, `succeeded` or `failed` once every attempt is used up.
+    pub status: String,
+    pub attempts: i64,
+    #[serde(rename = "lastStatusCode")]
+    pub last_status_code: Option<i64>,
+    #[serde(rename = "lastError")]
+    pub last_error: Option<String>,
+    #[serde(rename = "nextAttemptAt")]
+    pub next_attempt_at: Option<i64>,
+    #[serde(rename = "createdAt")]
+    pub created_at: i64,
+    #[serde(rename = "updatedAt")]
+    pub updated_at: i64,
+}
+
+#[derive(Serialize, Debug)]
+pub struct WebhookDeliveriesResponse {
+    pub status: String,
+    pub message: Option<String>,
+    pub deliveries: Vec<WebhookDelivery>,
     #[serde(rename = "statusCode")]
     pub status_code: i32,
 }
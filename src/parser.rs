@@ -2,6 +2,11 @@ use serde_json::{Value, from_value};
 use crate::errors::GatewayError;
 use crate::models::PaymentRequest;
 
+// Stripe's limits on object metadata.
+const MAX_METADATA_KEYS: usize = 50;
+const MAX_METADATA_KEY_LENGTH: usize = 40;
+const MAX_METADATA_VALUE_LENGTH: usize = 500;
+
 pub struct JsonRequestParser;
 
 impl JsonRequestParser {
@@ -9,19 +14,104 @@ impl JsonRequestParser {
         JsonRequestParser
     }
 
+    #[tracing::instrument(name = "parse_request", skip_all)]
     pub fn parse(&self, input: Value) -> Result<PaymentRequest, GatewayError> {
         let body = input.get("body")
             .ok_or_else(|| GatewayError::InvalidRequest("Request body is missing".to_string()))?;
 
-        if body.is_object() {
+        let request: PaymentRequest = if body.is_object() {
             from_value(body.clone())
-                .map_err(|e| GatewayError::SerializationError(e))
+                .map_err(GatewayError::SerializationError)?
         } else if body.is_string() {
             let body_str = body.as_str().unwrap();
             from_value(serde_json::from_str(body_str)?)
-                .map_err(|e| GatewayError::SerializationError(e))
+                .map_err(GatewayError::SerializationError)?
         } else {
-            Err(GatewayError::InvalidRequest("Body must be a JSON object or string".to_string()))
+            return Err(GatewayError::InvalidRequest("Body must be a JSON object or string".to_string()));
+        };
+
+        validate_metadata(&request)?;
+        Ok(request)
+    }
+}
+
+/// Rejects metadata Stripe would refuse, before any object is created.
+fn validate_metadata(request: &PaymentRequest) -> Result<(), GatewayError> {
+    let Some(metadata) = &request.metadata else {
+        return Ok(());
+    };
+    if metadata.len() > MAX_METADATA_KEYS {
+        return Err(GatewayError::InvalidRequest(format!("Metadata cannot have more than {} keys", MAX_METADATA_KEYS)));
+    }
+    for (key, value) in metadata {
+        if key.is_empty() || key.chars().count() > MAX_METADATA_KEY_LENGTH || key.contains(['[', ']']) {
+            return Err(GatewayError::InvalidRequest(format!(
+                "Invalid metadata key {:?}: keys must be 1-{} characters without square brackets",
+                key, MAX_METADATA_KEY_LENGTH,
+            )));
+        }
+        if value.chars().count() > MAX_METADATA_VALUE_LENGTH {
+            return Err(GatewayError::InvalidRequest(format!(
+                "Metadata value for {:?} exceeds {} characters",
+                key, MAX_METADATA_VALUE_LENGTH,
+            )));
         }
     }
-}
\ No newline at end of file
+    Ok(())
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn parse_with_metadata(metadata: Value) -> Result<PaymentRequest, GatewayError> {
+        JsonRequestParser::new().parse(json!({
+            "body": { "storeId": "store-a", "requestType": "CHARGE", "metadata": metadata },
+        }))
+    }
+
+    fn keys(count: usize) -> Value {
+        Value::Object((0..count).map(|i| (format!("key_{}", i), json!("value"))).collect())
+    }
+
+    fn is_invalid(result: Result<PaymentRequest, GatewayError>) -> bool {
+        matches!(result, Err(GatewayError::InvalidRequest(_)))
+    }
+
+    #[test]
+    fn accepts_up_to_fifty_keys() {
+        assert_eq!(parse_with_metadata(keys(50)).unwrap().metadata.unwrap().len(), 50);
+        assert!(is_invalid(parse_with_metadata(keys(51))));
+    }
+
+    #[test]
+    fn accepts_keys_up_to_forty_characters() {
+        assert!(parse_with_metadata(json!({ "k".repeat(40): "value" })).is_ok());
+        assert!(parse_with_metadata(json!({ "é".repeat(40): "value" })).is_ok());
+        assert!(is_invalid(parse_with_metadata(json!({ "k".repeat(41): "value" }))));
+        assert!(is_invalid(parse_with_metadata(json!({ "": "value" }))));
+    }
+
+    #[test]
+    fn accepts_values_up_to_five_hundred_characters() {
+        assert!(parse_with_metadata(json!({ "order": "v".repeat(500) })).is_ok());
+        assert!(parse_with_metadata(json!({ "order": "€".repeat(500) })).is_ok());
+        assert!(parse_with_metadata(json!({ "order": "" })).is_ok());
+        assert!(is_invalid(parse_with_metadata(json!({ "order": "v".repeat(501) }))));
+    }
+
+    #[test]
+    fn rejects_keys_with_square_brackets() {
+        for key in ["order[id]", "order[", "]", "[]"] {
+            assert!(is_invalid(parse_with_metadata(json!({ key: "value" }))), "{} was accepted", key);
+        }
+        assert!(parse_with_metadata(json!({ "order_id": "[1]" })).is_ok());
+    }
+
+    #[test]
+    fn string_bodies_are_validated_too() {
+        let body = json!({ "storeId": "store-a", "requestType": "CHARGE", "metadata": { "k".repeat(41): "v" } });
+        let result = JsonRequestParser::new().parse(json!({ "body": body.to_string() }));
+        assert!(is_invalid(result));
+    }
+}
@@ -1,33 +1,52 @@
 use async_trait::async_trait;
-use reqwest::{Client as HttpClient, Response};
-use serde_json::{json, Value};
+use base64::Engine;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::Arc;
 use urlencoding::encode;
 use crate::errors::GatewayError;
+use crate::ledger::{now_secs, DeliveryStore, ObjectState, RedemptionStore, TransactionStore, WebhookEventStore};
+use crate::merchant_webhooks::MerchantNotifier;
+use crate::metrics;
 use crate::models::{
-    PaymentRequest, ChargeResponse, PaymentLinkResponse, RefundResponse, PaymentStatusResponse, WebhookResponse
+    PaymentRequest, ChargeResponse, AuthorizationResponse, PaymentIntentResponse, PaymentLinkResponse, RefundResponse, PaymentStatusResponse,
+    WebhookResponse, AccountResponse, AccountLinkResponse, AccountRequirements, TransactionHistoryResponse,
+    WebhookDeliveriesResponse, ChargeDetails, PaymentMethodDetails, ListResponse, ListItem, DisputeDetails,
+    DisputeResponse, SessionLineItemsResponse, LineItem, SetupIntentResponse, ProductDetails, ProductResponse,
+    PriceDetails, PriceResponse, RecurringPrice, PriceTier, InvoiceDetails, InvoiceLine, InvoiceResponse,
+    BillingPortalResponse, AppliedDiscount, CouponDetails, CouponResponse, PromotionCodeDetails, PromotionCodeResponse,
+    BalanceAmount, BalanceResponse, BalanceTransactionDetails, BalanceTransactionResponse, FeeDetail, PayoutDetails,
+    PayoutResponse
 };
-
-async fn handle_stripe_response(response: Response) -> Result<Value, GatewayError> {
-    let status = response.status();
-    let body = response.json::<Value>().await?;
-    if status.is_success() {
-        Ok(body)
-    } else {
-        let message = body["error"]["message"].as_str()
-            .unwrap_or("Unknown Stripe error")
-            .to_string();
-        Err(GatewayError::InvalidRequest(message))
-    }
-}
+use crate::publisher::{EventEnvelope, EventRouter};
+use crate::stripe::StripeClient;
 
 #[async_trait]
 pub trait ChargeProcessor {
     async fn process_charge(&self, request: &PaymentRequest) -> Result<ChargeResponse, GatewayError>;
 }
 
+#[async_trait]
+pub trait AuthorizationProcessor {
+    async fn authorize(&self, request: &PaymentRequest) -> Result<AuthorizationResponse, GatewayError>;
+    async fn capture(&self, request: &PaymentRequest) -> Result<AuthorizationResponse, GatewayError>;
+    async fn void(&self, request: &PaymentRequest) -> Result<AuthorizationResponse, GatewayError>;
+}
+
+#[async_trait]
+pub trait SetupIntentProcessor {
+    async fn create_setup_intent(&self, request: &PaymentRequest) -> Result<SetupIntentResponse, GatewayError>;
+}
+
+#[async_trait]
+pub trait PaymentIntentProcessor {
+    async fn create_payment_intent(&self, request: &PaymentRequest) -> Result<PaymentIntentResponse, GatewayError>;
+}
+
 #[async_trait]
 pub trait PaymentLinkProcessor {
     async fn process_payment_link(&self, request: &PaymentRequest) -> Result<PaymentLinkResponse, GatewayError>;
+    async fn expire_session(&self, request: &PaymentRequest) -> Result<PaymentLinkResponse, GatewayError>;
 }
 
 #[async_trait]
@@ -38,6 +57,7 @@ pub trait RefundProcessor {
 #[async_trait]
 pub trait StatusProcessor {
     async fn process_status(&self, request: &PaymentRequest) -> Result<PaymentStatusResponse, GatewayError>;
+    async fn session_line_items(&self, request: &PaymentRequest) -> Result<SessionLineItemsResponse, GatewayError>;
 }
 
 #[async_trait]
@@ -45,16 +65,118 @@ pub trait WebhookProcessor {
     async fn process_webhook(&self, request: &PaymentRequest) -> Result<WebhookResponse, GatewayError>;
 }
 
+#[async_trait]
+pub trait AccountProcessor {
+    async fn create_account(&self, request: &PaymentRequest) -> Result<AccountResponse, GatewayError>;
+    async fn create_account_link(&self, request: &PaymentRequest) -> Result<AccountLinkResponse, GatewayError>;
+    async fn retrieve_account(&self, request: &PaymentRequest) -> Result<AccountResponse, GatewayError>;
+    async fn create_login_link(&self, request: &PaymentRequest) -> Result<AccountLinkResponse, GatewayError>;
+}
+
+#[async_trait]
+pub trait HistoryProcessor {
+    async fn process_history(&self, request: &PaymentRequest) -> Result<TransactionHistoryResponse, GatewayError>;
+}
+
+#[async_trait]
+pub trait ListProcessor {
+    async fn process_list(&self, request: &PaymentRequest) -> Result<ListResponse, GatewayError>;
+}
+
+#[async_trait]
+pub trait DisputeProcessor {
+    async fn list_disputes(&self, request: &PaymentRequest) -> Result<DisputeResponse, GatewayError>;
+    async fn retrieve_dispute(&self, request: &PaymentRequest) -> Result<DisputeResponse, GatewayError>;
+    async fn submit_evidence(&self, request: &PaymentRequest) -> Result<DisputeResponse, GatewayError>;
+    async fn close_dispute(&self, request: &PaymentRequest) -> Result<DisputeResponse, GatewayError>;
+}
+
+#[async_trait]
+pub trait InvoiceProcessor {
+    async fn create_invoice(&self, request: &PaymentRequest) -> Result<InvoiceResponse, GatewayError>;
+    async fn retrieve_invoice(&self, request: &PaymentRequest) -> Result<InvoiceResponse, GatewayError>;
+    async fn finalize_invoice(&self, request: &PaymentRequest) -> Result<InvoiceResponse, GatewayError>;
+    async fn send_invoice(&self, request: &PaymentRequest) -> Result<InvoiceResponse, GatewayError>;
+    async fn void_invoice(&self, request: &PaymentRequest) -> Result<InvoiceResponse, GatewayError>;
+    async fn mark_uncollectible(&self, request: &PaymentRequest) -> Result<InvoiceResponse, GatewayError>;
+    async fn pay_invoice(&self, request: &PaymentRequest) -> Result<InvoiceResponse, GatewayError>;
+}
+
+#[async_trait]
+pub trait BillingPortalProcessor {
+    async fn create_portal_session(&self, request: &PaymentRequest) -> Result<BillingPortalResponse, GatewayError>;
+}
+
+#[async_trait]
+pub trait DiscountProcessor {
+    async fn create_coupon(&self, request: &PaymentRequest) -> Result<CouponResponse, GatewayError>;
+    async fn list_coupons(&self, request: &PaymentRequest) -> Result<CouponResponse, GatewayError>;
+    async fn deactivate_coupon(&self, request: &PaymentRequest) -> Result<CouponResponse, GatewayError>;
+    async fn create_promotion_code(&self, request: &PaymentRequest) -> Result<PromotionCodeResponse, GatewayError>;
+    async fn list_promotion_codes(&self, request: &PaymentRequest) -> Result<PromotionCodeResponse, GatewayError>;
+    async fn deactivate_promotion_code(&self, request: &PaymentRequest) -> Result<PromotionCodeResponse, GatewayError>;
+}
+
+#[async_trait]
+pub trait BalanceProcessor {
+    async fn retrieve_balance(&self, request: &PaymentRequest) -> Result<BalanceResponse, GatewayError>;
+    async fn list_balance_transactions(&self, request: &PaymentRequest) -> Result<BalanceTransactionResponse, GatewayError>;
+    async fn list_payouts(&self, request: &PaymentRequest) -> Result<PayoutResponse, GatewayError>;
+    async fn retrieve_payout(&self, request: &PaymentRequest) -> Result<PayoutResponse, GatewayError>;
+}
+
+#[async_trait]
+pub trait CatalogProcessor {
+    async fn create_product(&self, request: &PaymentRequest) -> Result<ProductResponse, GatewayError>;
+    async fn update_product(&self, request: &PaymentRequest) -> Result<ProductResponse, GatewayError>;
+    async fn archive_product(&self, request: &PaymentRequest) -> Result<ProductResponse, GatewayError>;
+    async fn list_products(&self, request: &PaymentRequest) -> Result<ProductResponse, GatewayError>;
+    async fn create_price(&self, request: &PaymentRequest) -> Result<PriceResponse, GatewayError>;
+    async fn update_price(&self, request: &PaymentRequest) -> Result<PriceResponse, GatewayError>;
+    async fn archive_price(&self, request: &PaymentRequest) -> Result<PriceResponse, GatewayError>;
+    async fn list_prices(&self, request: &PaymentRequest) -> Result<PriceResponse, GatewayError>;
+}
+
+#[async_trait]
+pub trait DeliveryProcessor {
+    async fn list_deliveries(&self, request: &PaymentRequest) -> Result<WebhookDeliveriesResponse, GatewayError>;
+    async fn replay_delivery(&self, request: &PaymentRequest) -> Result<WebhookDeliveriesResponse, GatewayError>;
+    async fn retry_deliveries(&self, request: &PaymentRequest) -> Result<WebhookDeliveriesResponse, GatewayError>;
+}
+
 pub struct StripeChargeProcessor {
-    http_client: HttpClient,
-    api_key: String,
+    client: StripeClient,
+    redemptions: Arc<dyn RedemptionStore>,
 }
 
 impl StripeChargeProcessor {
-    pub fn new(api_key: String) -> Self {
+    pub fn new(client: StripeClient, redemptions: Arc<dyn RedemptionStore>) -> Self {
         StripeChargeProcessor {
-            http_client: HttpClient::new(),
-            api_key,
+            client,
+            redemptions,
+        }
+    }
+}
+
+/// Where a CHARGE takes the money from.
+enum ChargeSource<'a> {
+    /// A card token or source, charged through the Charges API.
+    Token(&'a str),
+    /// A customer's saved payment method, charged through a payment intent while they are away.
+    OffSession { customer_id: &'a str, payment_method_id: &'a str },
+}
+
+impl<'a> ChargeSource<'a> {
+    fn from_request(request: &'a PaymentRequest) -> Result<Self, GatewayError> {
+        if request.off_session == Some(true) {
+            match (&request.customer_id, &request.payment_method_id) {
+                (Some(customer_id), Some(payment_method_id)) => Ok(ChargeSource::OffSession { customer_id, payment_method_id }),
+                _ => Err(GatewayError::InvalidRequest("Customer ID and payment method ID are required for off-session charges".to_string())),
+            }
+        } else {
+            request.payment_token.as_deref()
+                .map(ChargeSource::Token)
+                .ok_or_else(|| GatewayError::InvalidRequest("Payment token is required".to_string()))
         }
     }
 }
@@ -62,108 +184,661 @@ impl StripeChargeProcessor {
 #[async_trait]
 impl ChargeProcessor for StripeChargeProcessor {
     async fn process_charge(&self, request: &PaymentRequest) -> Result<ChargeResponse, GatewayError> {
-        log::info!("Processing charge for store: {}", request.store_id);
-        if request.payment_token.is_none() {
-            return Err(GatewayError::InvalidRequest("Payment token is required".to_string()));
+        tracing::info!("Processing charge for store: {}", request.store_id);
+        // Checked before the discount is looked up, so an incomplete request makes no Stripe calls.
+        let source = ChargeSource::from_request(request)?;
+        let (amount, discount) = self.apply_discount(request).await?;
+        let result = match source {
+            ChargeSource::Token(token) => self.charge_source(request, token, amount, &discount).await,
+            ChargeSource::OffSession { customer_id, payment_method_id } => {
+                self.charge_off_session(request, customer_id, payment_method_id, amount, &discount).await
+            }
+        };
+        self.settle_redemptions(&request.store_id, &result, &discount).await;
+        let mut response = result?;
+        response.discount = discount;
+        Ok(response)
+    }
+}
+
+impl StripeChargeProcessor {
+    /// Takes the request's coupon or promotion code off its amount, once the promotion code's
+    /// restrictions are met. The Charges API has no notion of discounts, so Stripe does not
+    /// count the redemption: the ledger counts it against `max_redemptions` instead, and the
+    /// coupon's ID is kept in the charge's metadata.
+    async fn apply_discount(&self, request: &PaymentRequest) -> Result<(i64, Option<AppliedDiscount>), GatewayError> {
+        let amount = request.amount.unwrap_or(0);
+        let Some(discount) = resolve_discount(&self.client, request).await? else {
+            return Ok((amount, None));
+        };
+        let currency = request.currency.as_deref().unwrap_or("");
+        if let Some(promotion_code) = &discount.promotion_code {
+            self.check_restrictions(request, promotion_code, amount, currency).await?;
         }
-        let params = vec![
-            ("amount", request.amount.unwrap_or(0).to_string()),
+        let coupon = &discount.coupon;
+        let amount_discounted = discount_amount(coupon, amount, currency)?;
+        let applied = AppliedDiscount {
+            coupon_id: coupon["id"].as_str().map(String::from),
+            promotion_code_id: discount.promotion_code_id(),
+            original_amount: amount,
+            amount_discounted,
+        };
+        self.claim_redemptions(request, &discount, &applied).await?;
+        Ok((amount - amount_discounted, Some(applied)))
+    }
+
+    /// Enforces the promotion code's `minimum_amount`, on the amount before the discount, and
+    /// `first_time_transaction` restrictions, which Stripe only checks in Checkout and invoices.
+    async fn check_restrictions(&self, request: &PaymentRequest, promotion_code: &Value, amount: i64, currency: &str) -> Result<(), GatewayError> {
+        let restrictions = &promotion_code["restrictions"];
+        let code = promotion_code["code"].as_str().unwrap_or_default();
+        check_minimum_amount(promotion_code, amount, currency)?;
+        if restrictions["first_time_transaction"].as_bool() == Some(true) {
+            let customer_id = request.customer_id.as_deref()
+                .ok_or_else(|| GatewayError::InvalidRequest(format!("Promotion code is for first-time customers and needs a customer ID: {}", code)))?;
+            let params = [("customer", customer_id), ("limit", "100")];
+            let charges = self.client.get_with_params("/charges", &params).await?;
+            let has_paid = charges["data"].as_array()
+                .is_some_and(|data| data.iter().any(|charge| charge["status"].as_str() == Some("succeeded")));
+            if has_paid {
+                return Err(GatewayError::InvalidRequest(format!("Promotion code is only for first-time customers: {}", code)));
+            }
+        }
+        Ok(())
+    }
+
+    /// Counts the redemption against the coupon's and the promotion code's `max_redemptions`,
+    /// less the redemptions Stripe has already counted through Checkout and invoices.
+    async fn claim_redemptions(&self, request: &PaymentRequest, discount: &Discount, applied: &AppliedDiscount) -> Result<(), GatewayError> {
+        let remaining = |object: &Value| {
+            object["max_redemptions"].as_i64()
+                .map(|max_redemptions| max_redemptions - object["times_redeemed"].as_i64().unwrap_or(0))
+        };
+        let limits = std::iter::once(remaining(&discount.coupon))
+            .chain(discount.promotion_code.as_ref().map(remaining));
+
+        let mut claimed = Vec::new();
+        for (discount_id, limit) in redemption_ids(applied).into_iter().zip(limits) {
+            match self.redemptions.claim_redemption(&request.store_id, &discount_id, limit).await {
+                Ok(true) => claimed.push(discount_id),
+                result => {
+                    self.release_redemptions(&request.store_id, &claimed).await;
+                    result?;
+                    return Err(GatewayError::InvalidRequest(format!("Discount has reached its maximum redemptions: {}", discount_id)));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Gives back the redemptions of a charge that did not go through, or that is waiting for
+    /// the customer to authenticate it and may never be confirmed. A payment confirmed later
+    /// counts them again through `confirm_redemptions`.
+    async fn settle_redemptions(&self, store_id: &str, result: &Result<ChargeResponse, GatewayError>, discount: &Option<AppliedDiscount>) {
+        let Some(discount) = discount else {
+            return;
+        };
+        let charged = result.as_ref().is_ok_and(|response| response.payment_status.as_deref() != Some("requires_action"));
+        if !charged {
+            self.release_redemptions(store_id, &redemption_ids(discount)).await;
+        }
+    }
+
+    /// Counts the discount recorded in the metadata of an off-session payment intent the
+    /// customer has now confirmed. The discount was already granted, so no limit applies.
+    pub async fn confirm_redemptions(&self, store_id: &str, payment_intent: &Value) -> Result<(), GatewayError> {
+        let metadata = &payment_intent["metadata"];
+        for discount_id in redemption_keys(metadata["coupon_id"].as_str(), metadata["promotion_code_id"].as_str()) {
+            self.redemptions.claim_redemption(store_id, &discount_id, None).await?;
+        }
+        Ok(())
+    }
+
+    /// Gives back redemptions of a charge that did not go through. Failures are only logged,
+    /// since the charge's own error is the one to report.
+    async fn release_redemptions(&self, store_id: &str, discount_ids: &[String]) {
+        for discount_id in discount_ids {
+            if let Err(e) = self.redemptions.release_redemption(store_id, discount_id).await {
+                tracing::error!("Failed to release redemption of {}: {}", discount_id, e);
+            }
+        }
+    }
+
+    /// Metadata recording the discount, followed by the caller's own.
+    fn charge_params(params: &[(&str, String)], discount: &Option<AppliedDiscount>, request: &PaymentRequest) -> Vec<(String, String)> {
+        let mut params = params.to_vec();
+        if let Some(discount) = discount {
+            if let Some(coupon_id) = &discount.coupon_id {
+                params.push(("metadata[coupon_id]", coupon_id.clone()));
+            }
+            if let Some(promotion_code_id) = &discount.promotion_code_id {
+                params.push(("metadata[promotion_code_id]", promotion_code_id.clone()));
+            }
+        }
+        with_metadata(&params, &["metadata"], request)
+    }
+
+    async fn charge_source(&self, request: &PaymentRequest, token: &str, amount: i64, discount: &Option<AppliedDiscount>) -> Result<ChargeResponse, GatewayError> {
+        let params = [
+            ("amount", amount.to_string()),
             ("currency", request.currency.as_deref().unwrap_or("").to_string()),
-            ("source", request.payment_token.as_deref().unwrap_or("").to_string()),
+            ("source", token.to_string()),
             ("description", request.description.as_deref().unwrap_or("").to_string()),
         ];
-        let form_data: String = params.iter()
-            .map(|(k, v)| format!("{}={}", k, encode(v)))
-            .collect::<Vec<String>>()
-            .join("&");
 
-        let response = self.http_client.post("https://api.stripe.com/v1/charges")
-            .header("Authorization", format!("Bearer {}", self.api_key))
-            .header("Content-Type", "application/x-www-form-urlencoded")
-            .body(form_data)
-            .send()
-            .await?;
-
-        let body = handle_stripe_response(response).await?;
+        let body = self.client.post("/charges", &Self::charge_params(&params, discount, request)).await?;
 
         Ok(ChargeResponse {
             status: "success".to_string(),
             message: None,
             charge_id: body["id"].as_str().map(String::from),
+            payment_intent_id: None,
+            client_secret: None,
+            amount: body["amount"].as_i64(),
+            currency: body["currency"].as_str().map(String::from),
+            payment_status: body["status"].as_str().map(String::from),
+            discount: None,
+            metadata: metadata_of(&body),
+            status_code: 200,
+        })
+    }
+
+    /// Charges a customer's saved payment method while they are not present. When the
+    /// issuer asks for authentication, the declined payment intent is returned with its
+    /// client secret so the customer can be brought back to confirm it on-session.
+    async fn charge_off_session(
+        &self,
+        request: &PaymentRequest,
+        customer_id: &str,
+        payment_method_id: &str,
+        amount: i64,
+        discount: &Option<AppliedDiscount>,
+    ) -> Result<ChargeResponse, GatewayError> {
+        let params = [
+            ("amount", amount.to_string()),
+            ("currency", request.currency.as_deref().unwrap_or("").to_string()),
+            ("description", request.description.as_deref().unwrap_or("").to_string()),
+            ("customer", customer_id.to_string()),
+            ("payment_method", payment_method_id.to_string()),
+            ("off_session", "true".to_string()),
+            ("confirm", "true".to_string()),
+        ];
+
+        match self.client.post("/payment_intents", &Self::charge_params(&params, discount, request)).await {
+            Ok(body) => Ok(off_session_response(&body, 200)),
+            Err(GatewayError::AuthenticationRequired { message, payment_intent_id: Some(payment_intent_id) }) => {
+                tracing::info!("Off-session payment intent {} requires authentication", payment_intent_id);
+                let body = self.client.get(&format!("/payment_intents/{}", encode(&payment_intent_id))).await?;
+                Ok(ChargeResponse {
+                    status: "requires_action".to_string(),
+                    message: Some(message),
+                    client_secret: body["client_secret"].as_str().map(String::from),
+                    ..off_session_response(&body, 402)
+                })
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/// What the coupon takes off `amount`, which must leave something to charge.
+fn discount_amount(coupon: &Value, amount: i64, currency: &str) -> Result<i64, GatewayError> {
+    let amount_discounted = match (coupon["percent_off"].as_f64(), coupon["amount_off"].as_i64()) {
+        (Some(percent_off), _) => (amount as f64 * percent_off / 100.0).round() as i64,
+        (None, Some(amount_off)) if coupon["currency"].as_str().is_some_and(|c| c.eq_ignore_ascii_case(currency)) => amount_off,
+        (None, Some(_)) => return Err(GatewayError::InvalidRequest("Coupon is not valid in this currency".to_string())),
+        (None, None) => return Err(GatewayError::InvalidRequest("Coupon has no discount".to_string())),
+    }.min(amount);
+    if amount - amount_discounted <= 0 {
+        return Err(GatewayError::InvalidRequest("Discounted amount must be positive".to_string()));
+    }
+    Ok(amount_discounted)
+}
+
+/// Enforces a promotion code's `minimum_amount` in the charge's currency, preferring its
+/// per-currency `currency_options`.
+fn check_minimum_amount(promotion_code: &Value, amount: i64, currency: &str) -> Result<(), GatewayError> {
+    let restrictions = &promotion_code["restrictions"];
+    if !restrictions["minimum_amount"].is_i64() {
+        return Ok(());
+    }
+    let code = promotion_code["code"].as_str().unwrap_or_default();
+    let currency = currency.to_lowercase();
+    let minimum_amount = restrictions["currency_options"][currency.as_str()]["minimum_amount"].as_i64()
+        .or_else(|| {
+            restrictions["minimum_amount"].as_i64()
+                .filter(|_| restrictions["minimum_amount_currency"].as_str().is_some_and(|c| c.eq_ignore_ascii_case(&currency)))
+        })
+        .ok_or_else(|| GatewayError::InvalidRequest(format!("Promotion code is not valid in this currency: {}", code)))?;
+    if amount < minimum_amount {
+        return Err(GatewayError::InvalidRequest(format!("Promotion code {} requires a minimum amount of {}", code, minimum_amount)));
+    }
+    Ok(())
+}
+
+/// Ledger keys of the coupon and promotion code a charge redeemed, in that order.
+fn redemption_ids(discount: &AppliedDiscount) -> Vec<String> {
+    redemption_keys(discount.coupon_id.as_deref(), discount.promotion_code_id.as_deref())
+}
+
+fn redemption_keys(coupon_id: Option<&str>, promotion_code_id: Option<&str>) -> Vec<String> {
+    coupon_id.iter().map(|id| format!("coupon:{}", id))
+        .chain(promotion_code_id.iter().map(|id| format!("promotion_code:{}", id)))
+        .collect()
+}
+
+fn off_session_response(payment_intent: &Value, status_code: i32) -> ChargeResponse {
+    ChargeResponse {
+        status: "success".to_string(),
+        message: None,
+        charge_id: id_of(&payment_intent["latest_charge"]),
+        payment_intent_id: payment_intent["id"].as_str().map(String::from),
+        client_secret: None,
+        amount: payment_intent["amount"].as_i64(),
+        currency: payment_intent["currency"].as_str().map(String::from),
+        payment_status: payment_intent["status"].as_str().map(String::from),
+        discount: None,
+        metadata: metadata_of(payment_intent),
+        status_code,
+    }
+}
+
+/// Saves payment methods to customers without charging them, for later off-session charges.
+pub struct StripeSetupIntentProcessor {
+    client: StripeClient,
+}
+
+impl StripeSetupIntentProcessor {
+    pub fn new(client: StripeClient) -> Self {
+        StripeSetupIntentProcessor {
+            client,
+        }
+    }
+}
+
+#[async_trait]
+impl SetupIntentProcessor for StripeSetupIntentProcessor {
+    async fn create_setup_intent(&self, request: &PaymentRequest) -> Result<SetupIntentResponse, GatewayError> {
+        tracing::info!("Creating setup intent for store: {}", request.store_id);
+        let customer_id = request.customer_id.as_deref()
+            .ok_or_else(|| GatewayError::InvalidRequest("Customer ID is required".to_string()))?;
+        let usage = request.usage.as_deref().unwrap_or("off_session");
+        if !matches!(usage, "off_session" | "on_session") {
+            return Err(GatewayError::InvalidRequest(format!("Unsupported usage: {}", usage)));
+        }
+
+        let mut params = vec![
+            ("customer", customer_id.to_string()),
+            ("usage", usage.to_string()),
+        ];
+        match &request.payment_method_types {
+            Some(types) => params.extend(types.iter().map(|method_type| ("payment_method_types[]", method_type.clone()))),
+            None => params.push(("automatic_payment_methods[enabled]", "true".to_string())),
+        }
+        // A payment method already collected is confirmed right away; otherwise the client
+        // secret is used to collect and confirm one in the browser.
+        if let Some(payment_method_id) = &request.payment_method_id {
+            params.push(("payment_method", payment_method_id.clone()));
+            params.push(("confirm", "true".to_string()));
+            if let Some(return_url) = &request.return_url {
+                params.push(("return_url", return_url.clone()));
+            }
+        }
+
+        let body = self.client.post("/setup_intents", &with_metadata(&params, &["metadata"], request)).await?;
+
+        Ok(SetupIntentResponse {
+            status: "success".to_string(),
+            message: None,
+            setup_intent_id: body["id"].as_str().map(String::from),
+            client_secret: body["client_secret"].as_str().map(String::from),
+            setup_status: body["status"].as_str().map(String::from),
+            customer_id: id_of(&body["customer"]),
+            payment_method_id: id_of(&body["payment_method"]),
+            usage: body["usage"].as_str().map(String::from),
+            metadata: metadata_of(&body),
+            status_code: 200,
+        })
+    }
+}
+
+/// Authorizes card payments as manually captured payment intents, to be captured (in full or
+/// in part) or voided later.
+pub struct StripeAuthorizationProcessor {
+    client: StripeClient,
+}
+
+impl StripeAuthorizationProcessor {
+    pub fn new(client: StripeClient) -> Self {
+        StripeAuthorizationProcessor {
+            client,
+        }
+    }
+
+    fn payment_intent_id(request: &PaymentRequest) -> Result<&str, GatewayError> {
+        request.payment_intent_id.as_deref()
+            .ok_or_else(|| GatewayError::InvalidRequest("Payment intent ID is required".to_string()))
+    }
+}
+
+#[async_trait]
+impl AuthorizationProcessor for StripeAuthorizationProcessor {
+    async fn authorize(&self, request: &PaymentRequest) -> Result<AuthorizationResponse, GatewayError> {
+        tracing::info!("Processing authorization for store: {}", request.store_id);
+        let payment_token = request.payment_token.as_deref()
+            .ok_or_else(|| GatewayError::InvalidRequest("Payment token is required".to_string()))?;
+
+        let mut params = vec![
+            ("amount", request.amount.unwrap_or(0).to_string()),
+            ("currency", request.currency.as_deref().unwrap_or("").to_string()),
+            ("description", request.description.as_deref().unwrap_or("").to_string()),
+            ("capture_method", "manual".to_string()),
+            ("confirm", "true".to_string()),
+            ("payment_method_types[]", "card".to_string()),
+            ("expand[]", "latest_charge".to_string()),
+        ];
+        // Accept saved payment methods as well as the card tokens CHARGE takes.
+        if payment_token.starts_with("pm_") {
+            params.push(("payment_method", payment_token.to_string()));
+        } else {
+            params.push(("payment_method_data[type]", "card".to_string()));
+            params.push(("payment_method_data[card][token]", payment_token.to_string()));
+        }
+
+        let body = self.client.post("/payment_intents", &with_metadata(&params, &["metadata"], request)).await?;
+        Ok(authorization_response(&body))
+    }
+
+    async fn capture(&self, request: &PaymentRequest) -> Result<AuthorizationResponse, GatewayError> {
+        let payment_intent_id = Self::payment_intent_id(request)?;
+        tracing::info!("Capturing payment intent: {}", payment_intent_id);
+
+        let mut params = vec![("expand[]", "latest_charge".to_string())];
+        if let Some(amount) = request.amount {
+            params.push(("amount_to_capture", amount.to_string()));
+        }
+
+        let body = self.client.post(&format!("/payment_intents/{}/capture", encode(payment_intent_id)), &params).await?;
+        Ok(authorization_response(&body))
+    }
+
+    async fn void(&self, request: &PaymentRequest) -> Result<AuthorizationResponse, GatewayError> {
+        let payment_intent_id = Self::payment_intent_id(request)?;
+        tracing::info!("Voiding payment intent: {}", payment_intent_id);
+
+        let mut params = vec![("expand[]", "latest_charge".to_string())];
+        if let Some(reason) = &request.cancellation_reason {
+            params.push(("cancellation_reason", reason.clone()));
+        }
+
+        let body = self.client.post(&format!("/payment_intents/{}/cancel", encode(payment_intent_id)), &params).await?;
+        Ok(authorization_response(&body))
+    }
+}
+
+fn authorization_response(payment_intent: &Value) -> AuthorizationResponse {
+    let charge = &payment_intent["latest_charge"];
+    AuthorizationResponse {
+        status: "success".to_string(),
+        message: None,
+        payment_intent_id: payment_intent["id"].as_str().map(String::from),
+        charge_id: id_of(charge),
+        amount: payment_intent["amount"].as_i64(),
+        amount_capturable: payment_intent["amount_capturable"].as_i64(),
+        amount_received: payment_intent["amount_received"].as_i64(),
+        currency: payment_intent["currency"].as_str().map(String::from),
+        payment_status: payment_intent["status"].as_str().map(String::from),
+        capture_before: charge["payment_method_details"]["card"]["capture_before"].as_i64(),
+        metadata: metadata_of(payment_intent),
+        status_code: 200,
+    }
+}
+
+/// Creates payment intents for the browser to collect and confirm with Stripe Elements.
+pub struct StripePaymentIntentProcessor {
+    client: StripeClient,
+}
+
+impl StripePaymentIntentProcessor {
+    pub fn new(client: StripeClient) -> Self {
+        StripePaymentIntentProcessor {
+            client,
+        }
+    }
+}
+
+#[async_trait]
+impl PaymentIntentProcessor for StripePaymentIntentProcessor {
+    async fn create_payment_intent(&self, request: &PaymentRequest) -> Result<PaymentIntentResponse, GatewayError> {
+        tracing::info!("Creating payment intent for store: {}", request.store_id);
+        if request.amount.is_none() || request.currency.is_none() {
+            return Err(GatewayError::InvalidRequest("Amount and currency are required".to_string()));
+        }
+
+        let mut params = vec![
+            ("amount", request.amount.unwrap_or(0).to_string()),
+            ("currency", request.currency.as_deref().unwrap_or("").to_string()),
+            ("description", request.description.as_deref().unwrap_or("").to_string()),
+        ];
+        match &request.payment_method_types {
+            Some(types) => params.extend(types.iter().map(|method_type| ("payment_method_types[]", method_type.clone()))),
+            None => params.push(("automatic_payment_methods[enabled]", "true".to_string())),
+        }
+        if let Some(customer_id) = &request.customer_id {
+            params.push(("customer", customer_id.clone()));
+        }
+
+        let body = self.client.post("/payment_intents", &with_metadata(&params, &["metadata"], request)).await?;
+
+        Ok(PaymentIntentResponse {
+            status: "success".to_string(),
+            message: None,
+            payment_intent_id: body["id"].as_str().map(String::from),
+            client_secret: body["client_secret"].as_str().map(String::from),
             amount: body["amount"].as_i64(),
             currency: body["currency"].as_str().map(String::from),
+            payment_status: body["status"].as_str().map(String::from),
+            metadata: metadata_of(&body),
             status_code: 200,
         })
     }
 }
 
 pub struct StripePaymentLinkProcessor {
-    http_client: HttpClient,
-    api_key: String,
+    client: StripeClient,
 }
 
 impl StripePaymentLinkProcessor {
-    pub fn new(api_key: String) -> Self {
+    pub fn new(client: StripeClient) -> Self {
         StripePaymentLinkProcessor {
-            http_client: HttpClient::new(),
-            api_key,
+            client,
+        }
+    }
+}
+
+impl StripePaymentLinkProcessor {
+    /// Translates the optional Checkout customizations into session parameters.
+    fn checkout_options(request: &PaymentRequest) -> Result<Vec<(String, String)>, GatewayError> {
+        let mut params = Vec::new();
+        let mut push = |key: String, value: String| params.push((key, value));
+
+        if let Some(customer_id) = &request.customer_id {
+            push("customer".to_string(), customer_id.clone());
+        }
+        if let Some(email) = &request.customer_email {
+            push("customer_email".to_string(), email.clone());
+        }
+        if let Some(reference) = &request.client_reference_id {
+            push("client_reference_id".to_string(), reference.clone());
+        }
+        for method_type in request.payment_method_types.iter().flatten() {
+            push("payment_method_types[]".to_string(), method_type.clone());
+        }
+        if let Some(collection) = &request.billing_address_collection {
+            if collection != "auto" && collection != "required" {
+                return Err(GatewayError::InvalidRequest(format!("Invalid billing address collection: {}", collection)));
+            }
+            push("billing_address_collection".to_string(), collection.clone());
+        }
+        for country in request.shipping_countries.iter().flatten() {
+            push("shipping_address_collection[allowed_countries][]".to_string(), country.clone());
+        }
+        for (index, rate) in request.shipping_rates.iter().flatten().enumerate() {
+            push(format!("shipping_options[{}][shipping_rate]", index), rate.clone());
+        }
+        if let Some(enabled) = request.phone_number_collection {
+            push("phone_number_collection[enabled]".to_string(), enabled.to_string());
         }
+        if let Some(allow) = request.allow_promotion_codes {
+            push("allow_promotion_codes".to_string(), allow.to_string());
+        }
+        if let Some(locale) = &request.locale {
+            push("locale".to_string(), locale.clone());
+        }
+        for (index, field) in request.custom_fields.iter().flatten().enumerate() {
+            let prefix = format!("custom_fields[{}]", index);
+            if !["text", "numeric", "dropdown"].contains(&field.field_type.as_str()) {
+                return Err(GatewayError::InvalidRequest(format!("Invalid custom field type: {}", field.field_type)));
+            }
+            push(format!("{}[key]", prefix), field.key.clone());
+            push(format!("{}[label][type]", prefix), "custom".to_string());
+            push(format!("{}[label][custom]", prefix), field.label.clone());
+            push(format!("{}[type]", prefix), field.field_type.clone());
+            push(format!("{}[optional]", prefix), field.optional.to_string());
+            for (option_index, option) in field.options.iter().enumerate() {
+                push(format!("{}[dropdown][options][{}][label]", prefix, option_index), option.label.clone());
+                push(format!("{}[dropdown][options][{}][value]", prefix, option_index), option.value.clone());
+            }
+        }
+        if let Some(expires_at) = request.expires_at {
+            push("expires_at".to_string(), expires_at.to_string());
+        }
+        if let Some(enabled) = request.automatic_tax {
+            push("automatic_tax[enabled]".to_string(), enabled.to_string());
+        }
+        Ok(params)
     }
 }
 
 #[async_trait]
 impl PaymentLinkProcessor for StripePaymentLinkProcessor {
     async fn process_payment_link(&self, request: &PaymentRequest) -> Result<PaymentLinkResponse, GatewayError> {
-        log::info!("Processing payment link for store: {}", request.store_id);
-        if request.success_url.is_none() || request.cancel_url.is_none() {
-            return Err(GatewayError::InvalidRequest("Success and cancel URLs are required".to_string()));
-        }
+        tracing::info!("Processing payment link for store: {}", request.store_id);
+        let ui_mode = request.ui_mode.as_deref().unwrap_or("hosted").to_lowercase();
 
         let currency = request.currency.as_deref().unwrap_or("");
         let amount = request.amount.unwrap_or(0);
         let description = request.description.as_deref().unwrap_or("");
-        let success_url = request.success_url.as_deref().unwrap_or("");
-        let cancel_url = request.cancel_url.as_deref().unwrap_or("");
 
-        let form_data = format!(
-            "mode=payment&line_items[0][price_data][currency]={}&line_items[0][price_data][unit_amount]={}&line_items[0][price_data][product_data][name]={}&line_items[0][quantity]=1&success_url={}&cancel_url={}",
-            encode(currency),
-            amount,
-            encode(description),
-            encode(success_url),
-            encode(cancel_url)
-        );
+        let quantity = request.quantity.unwrap_or(1).to_string();
 
-        log::debug!("Form data: {}", form_data);
-
-        let response = self.http_client.post("https://api.stripe.com/v1/checkout/sessions")
-            .header("Authorization", format!("Bearer {}", self.api_key))
-            .header("Content-Type", "application/x-www-form-urlencoded")
-            .body(form_data)
-            .send()
-            .await?;
+        // Catalog prices are sold by ID; recurring ones start a subscription.
+        let (mut params, metadata_prefixes) = match &request.price_id {
+            Some(price_id) => {
+                let price = self.client.get(&format!("/prices/{}", encode(price_id))).await?;
+                let mode = if price["type"] == "recurring" { "subscription" } else { "payment" };
+                let metadata_prefix = if mode == "subscription" { "subscription_data[metadata]" } else { "payment_intent_data[metadata]" };
+                let params = vec![
+                    ("mode", mode.to_string()),
+                    ("line_items[0][price]", price_id.clone()),
+                    ("line_items[0][quantity]", quantity),
+                ];
+                (params, ["metadata", metadata_prefix])
+            }
+            None => {
+                let params = vec![
+                    ("mode", "payment".to_string()),
+                    ("line_items[0][price_data][currency]", currency.to_string()),
+                    ("line_items[0][price_data][unit_amount]", amount.to_string()),
+                    ("line_items[0][price_data][product_data][name]", description.to_string()),
+                    ("line_items[0][quantity]", quantity),
+                ];
+                (params, ["metadata", "payment_intent_data[metadata]"])
+            }
+        };
+        match ui_mode.as_str() {
+            "hosted" => {
+                if request.success_url.is_none() || request.cancel_url.is_none() {
+                    return Err(GatewayError::InvalidRequest("Success and cancel URLs are required".to_string()));
+                }
+                params.push(("success_url", request.success_url.as_deref().unwrap_or("").to_string()));
+                params.push(("cancel_url", request.cancel_url.as_deref().unwrap_or("").to_string()));
+            }
+            // The page stays on-site; Stripe sends the customer to the return URL afterwards.
+            "embedded" | "custom" => {
+                let return_url = request.return_url.as_deref()
+                    .ok_or_else(|| GatewayError::InvalidRequest("Return URL is required".to_string()))?;
+                params.push(("ui_mode", ui_mode.clone()));
+                params.push(("return_url", return_url.to_string()));
+            }
+            other => return Err(GatewayError::InvalidRequest(format!("Unsupported UI mode: {}", other))),
+        }
 
-        let body = handle_stripe_response(response).await?;
+        // Copied onto the payment intent (or subscription) too, so what it creates carries it.
+        let mut params = with_metadata(&params, &metadata_prefixes, request);
+        params.extend(Self::checkout_options(request)?);
+        if let Some(discount) = resolve_discount(&self.client, request).await? {
+            if request.allow_promotion_codes == Some(true) {
+                return Err(GatewayError::InvalidRequest("A discount cannot be applied when customers may enter promotion codes".to_string()));
+            }
+            match (discount.promotion_code_id(), discount.coupon["id"].as_str()) {
+                (Some(promotion_code_id), _) => params.push(("discounts[0][promotion_code]".to_string(), promotion_code_id)),
+                (None, Some(coupon_id)) => params.push(("discounts[0][coupon]".to_string(), coupon_id.to_string())),
+                (None, None) => {}
+            }
+        }
+        let body = self.client.post("/checkout/sessions", &params).await?;
 
         Ok(PaymentLinkResponse {
             status: "success".to_string(),
             message: None,
             payment_link: body["url"].as_str().map(String::from),
+            session_id: body["id"].as_str().map(String::from),
+            session_status: body["status"].as_str().map(String::from),
+            amount_total: body["amount_total"].as_i64(),
+            currency: body["currency"].as_str().map(String::from),
+            client_secret: body["client_secret"].as_str().map(String::from),
+            metadata: metadata_of(&body),
+            status_code: 200,
+        })
+    }
+
+    /// Expires an open session so it can no longer be paid.
+    async fn expire_session(&self, request: &PaymentRequest) -> Result<PaymentLinkResponse, GatewayError> {
+        let session_id = required_session_id(request)?;
+        tracing::info!("Expiring checkout session: {}", session_id);
+
+        let body = self.client.post::<&str, &str>(&format!("/checkout/sessions/{}/expire", encode(session_id)), &[]).await?;
+
+        Ok(PaymentLinkResponse {
+            status: "success".to_string(),
+            message: None,
+            payment_link: None,
+            session_id: body["id"].as_str().map(String::from),
+            session_status: body["status"].as_str().map(String::from),
+            amount_total: body["amount_total"].as_i64(),
+            currency: body["currency"].as_str().map(String::from),
+            client_secret: None,
+            metadata: metadata_of(&body),
             status_code: 200,
         })
     }
 }
 
+fn required_session_id(request: &PaymentRequest) -> Result<&str, GatewayError> {
+    request.session_id.as_deref()
+        .filter(|id| !id.is_empty())
+        .ok_or_else(|| GatewayError::InvalidRequest("Session ID is required".to_string()))
+}
+
 pub struct StripeRefundProcessor {
-    http_client: HttpClient,
-    api_key: String,
+    client: StripeClient,
 }
 
 impl StripeRefundProcessor {
-    pub fn new(api_key: String) -> Self {
+    pub fn new(client: StripeClient) -> Self {
         StripeRefundProcessor {
-            http_client: HttpClient::new(),
-            api_key,
+            client,
         }
     }
 }
@@ -171,7 +846,7 @@ impl StripeRefundProcessor {
 #[async_trait]
 impl RefundProcessor for StripeRefundProcessor {
     async fn process_refund(&self, request: &PaymentRequest) -> Result<RefundResponse, GatewayError> {
-        log::info!("Processing refund for store: {}", request.store_id);
+        tracing::info!("Processing refund for store: {}", request.store_id);
         if request.charge_id.is_none() {
             return Err(GatewayError::InvalidRequest("Charge ID is required".to_string()));
         }
@@ -179,22 +854,12 @@ impl RefundProcessor for StripeRefundProcessor {
         let charge_id = request.charge_id.as_deref().unwrap_or("");
         let amount = request.amount.unwrap_or(0);
 
-        let form_data = format!(
-            "charge={}&amount={}",
-            encode(charge_id),
-            amount
-        );
-
-        log::debug!("Form data: {}", form_data);
-
-        let response = self.http_client.post("https://api.stripe.com/v1/refunds")
-            .header("Authorization", format!("Bearer {}", self.api_key))
-            .header("Content-Type", "application/x-www-form-urlencoded")
-            .body(form_data)
-            .send()
-            .await?;
+        let params = [
+            ("charge", charge_id.to_string()),
+            ("amount", amount.to_string()),
+        ];
 
-        let body = handle_stripe_response(response).await?;
+        let body = self.client.post("/refunds", &with_metadata(&params, &["metadata"], request)).await?;
 
         Ok(RefundResponse {
             status: "success".to_string(),
@@ -202,21 +867,80 @@ impl RefundProcessor for StripeRefundProcessor {
             refund_id: body["id"].as_str().map(String::from),
             amount: body["amount"].as_i64(),
             currency: body["currency"].as_str().map(String::from),
+            refund_status: body["status"].as_str().map(String::from),
+            metadata: metadata_of(&body),
             status_code: 200,
         })
     }
 }
 
 pub struct StripeStatusProcessor {
-    http_client: HttpClient,
-    api_key: String,
+    client: StripeClient,
 }
 
 impl StripeStatusProcessor {
-    pub fn new(api_key: String) -> Self {
+    pub fn new(client: StripeClient) -> Self {
         StripeStatusProcessor {
-            http_client: HttpClient::new(),
-            api_key,
+            client,
+        }
+    }
+}
+
+/// A Stripe object type STATUS can retrieve.
+struct RetrievableObject {
+    /// The request's `objectType`.
+    name: &'static str,
+    /// ID prefixes used to infer the type when `objectType` is omitted.
+    prefixes: &'static [&'static str],
+    path: &'static str,
+    /// Expansions needed to reach the underlying charge.
+    charge_expansions: &'static [&'static str],
+}
+
+const RETRIEVABLE_OBJECTS: &[RetrievableObject] = &[
+    RetrievableObject { name: "charge", prefixes: &["ch_", "py_"], path: "/charges", charge_expansions: &[] },
+    RetrievableObject { name: "payment_intent", prefixes: &["pi_"], path: "/payment_intents", charge_expansions: &["latest_charge"] },
+    RetrievableObject { name: "refund", prefixes: &["re_", "pyr_"], path: "/refunds", charge_expansions: &["charge"] },
+    RetrievableObject { name: "dispute", prefixes: &["dp_", "du_"], path: "/disputes", charge_expansions: &["charge"] },
+    RetrievableObject { name: "customer", prefixes: &["cus_"], path: "/customers", charge_expansions: &[] },
+    RetrievableObject { name: "payout", prefixes: &["po_"], path: "/payouts", charge_expansions: &[] },
+    RetrievableObject {
+        name: "checkout_session",
+        prefixes: &["cs_"],
+        path: "/checkout/sessions",
+        charge_expansions: &["payment_intent.latest_charge"],
+    },
+];
+
+impl StripeStatusProcessor {
+    /// Resolves the object from `objectType` + `objectId`, or from the prefix of `objectId`
+    /// alone, or else from the legacy `chargeId`/`sessionId` fields. `objectId` cannot be
+    /// combined with the legacy fields, as it would be unclear which object was meant.
+    fn target(request: &PaymentRequest) -> Result<(&'static RetrievableObject, String), GatewayError> {
+        if request.object_id.is_some() && (request.charge_id.is_some() || request.session_id.is_some()) {
+            return Err(GatewayError::InvalidRequest("Send either objectId or chargeId/sessionId, not both".to_string()));
+        }
+        let (object_type, object_id) = match (&request.object_type, &request.object_id, &request.charge_id, &request.session_id) {
+            (Some(object_type), Some(object_id), _, _) => (Some(object_type.to_lowercase()), object_id.clone()),
+            (Some(_), None, _, _) => return Err(GatewayError::InvalidRequest("Object ID is required".to_string())),
+            (None, _, Some(charge_id), _) => (Some("charge".to_string()), charge_id.clone()),
+            (None, _, None, Some(session_id)) => (Some("checkout_session".to_string()), session_id.clone()),
+            (None, Some(object_id), None, None) => (None, object_id.clone()),
+            (None, None, None, None) => {
+                return Err(GatewayError::InvalidRequest("Charge ID or Session ID required".to_string()));
+            }
+        };
+
+        let target = RETRIEVABLE_OBJECTS.iter().find(|object| match &object_type {
+            Some(object_type) => object.name == object_type,
+            None => object.prefixes.iter().any(|prefix| object_id.starts_with(prefix)),
+        });
+        match target {
+            Some(target) => Ok((target, object_id)),
+            None => Err(GatewayError::InvalidRequest(format!(
+                "Unsupported object type: {}",
+                object_type.unwrap_or(object_id),
+            ))),
         }
     }
 }
@@ -224,53 +948,387 @@ impl StripeStatusProcessor {
 #[async_trait]
 impl StatusProcessor for StripeStatusProcessor {
     async fn process_status(&self, request: &PaymentRequest) -> Result<PaymentStatusResponse, GatewayError> {
-        log::info!("Processing status check for store: {}", request.store_id);
-        if request.charge_id.is_none() && request.session_id.is_none() {
-            return Err(GatewayError::InvalidRequest("Charge ID or Session ID required".to_string()));
+        tracing::info!("Processing status check for store: {}", request.store_id);
+        let (target, object_id) = Self::target(request)?;
+        let object_type = target.name;
+        let requested = request.expand.clone().unwrap_or_default();
+
+        let mut expand: Vec<(&str, &str)> = target.charge_expansions.iter().map(|path| ("expand[]", *path)).collect();
+        for path in &requested {
+            if !target.charge_expansions.contains(&path.as_str()) {
+                expand.push(("expand[]", path));
+            }
         }
-        let (url, payment_id) = if let Some(charge_id) = &request.charge_id {
-            (format!("https://api.stripe.com/v1/charges/{}", encode(charge_id)), charge_id.clone())
-        } else {
-            let session_id = request.session_id.as_deref().unwrap_or("");
-            (format!("https://api.stripe.com/v1/checkout/sessions/{}", encode(session_id)), session_id.to_string())
+        let body = self.client.get_with_params(&format!("{}/{}", target.path, encode(&object_id)), &expand).await?;
+
+        let charge = match object_type {
+            "charge" => Some(&body),
+            "payment_intent" => Some(&body["latest_charge"]),
+            "refund" | "dispute" => Some(&body["charge"]),
+            "checkout_session" => Some(&body["payment_intent"]["latest_charge"]),
+            _ => None,
+        }.filter(|charge| charge.is_object());
+
+        let (failure_code, failure_message) = match object_type {
+            "payment_intent" => (&body["last_payment_error"]["code"], &body["last_payment_error"]["message"]),
+            "refund" => (&body["failure_reason"], &Value::Null),
+            "dispute" => (&body["reason"], &Value::Null),
+            _ => (&body["failure_code"], &body["failure_message"]),
         };
-        let response = self.http_client.get(&url)
-            .header("Authorization", format!("Bearer {}", self.api_key))
-            .send()
-            .await?;
 
-        let body = handle_stripe_response(response).await?;
+        let expanded = (!requested.is_empty()).then(|| {
+            requested.iter()
+                .map(|path| (path.clone(), path.split('.').fold(&body, |value, key| &value[key]).clone()))
+                .collect()
+        });
 
         Ok(PaymentStatusResponse {
             status: "success".to_string(),
             message: None,
-            payment_id: Some(payment_id),
+            payment_id: Some(object_id),
+            object_type: Some(object_type.to_string()),
             payment_status: body["status"].as_str().map(String::from),
             amount: body["amount"].as_i64().or_else(|| body["amount_total"].as_i64()),
             currency: body["currency"].as_str().map(String::from),
+            customer_id: id_of(&body["customer"]),
+            created: body["created"].as_i64(),
+            failure_code: failure_code.as_str().map(String::from),
+            failure_message: failure_message.as_str().map(String::from),
+            charge: charge.map(charge_details),
+            expanded,
+            metadata: metadata_of(&body),
+            status_code: 200,
+        })
+    }
+
+    async fn session_line_items(&self, request: &PaymentRequest) -> Result<SessionLineItemsResponse, GatewayError> {
+        let session_id = required_session_id(request)?;
+        tracing::info!("Retrieving line items for checkout session: {}", session_id);
+        let path = format!("/checkout/sessions/{}", encode(session_id));
+
+        if request.created_gte.is_some() || request.created_lte.is_some() {
+            return Err(GatewayError::InvalidRequest("Line items cannot be filtered by creation time".to_string()));
+        }
+
+        let session = self.client.get(&path).await?;
+        let params = list_page_params(request);
+        let body = self.client.get_with_params(&format!("{}/line_items", path), &params).await?;
+        let data = body["data"].as_array().cloned().unwrap_or_default();
+        let has_more = body["has_more"].as_bool().unwrap_or(false);
+
+        Ok(SessionLineItemsResponse {
+            status: "success".to_string(),
+            message: None,
+            session_id: session["id"].as_str().map(String::from),
+            session_status: session["status"].as_str().map(String::from),
+            payment_intent_id: id_of(&session["payment_intent"]),
+            customer_id: id_of(&session["customer"]),
+            line_items: data.iter().map(line_item).collect(),
+            has_more,
+            next_cursor: next_cursor(&data, has_more),
             status_code: 200,
         })
     }
 }
 
-pub struct StripeWebhookProcessor {
-    _http_client: HttpClient,
-    _api_key: String,
+fn line_item(item: &Value) -> LineItem {
+    let price = &item["price"];
+    LineItem {
+        id: item["id"].as_str().map(String::from),
+        description: item["description"].as_str().map(String::from),
+        quantity: item["quantity"].as_i64(),
+        price_id: price["id"].as_str().map(String::from),
+        product_id: id_of(&price["product"]),
+        unit_amount: price["unit_amount"].as_i64(),
+        currency: item["currency"].as_str().map(String::from),
+        amount_subtotal: item["amount_subtotal"].as_i64(),
+        amount_discount: item["amount_discount"].as_i64(),
+        amount_tax: item["amount_tax"].as_i64(),
+        amount_total: item["amount_total"].as_i64(),
+    }
 }
 
-impl StripeWebhookProcessor {
-    pub fn new(api_key: String) -> Self {
-        StripeWebhookProcessor {
-            _http_client: HttpClient::new(),
-            _api_key: api_key,
+/// Appends the request's metadata to `params` under each of `prefixes`, e.g. `metadata[order_id]`.
+fn with_metadata<K: AsRef<str>>(params: &[(K, String)], prefixes: &[&str], request: &PaymentRequest) -> Vec<(String, String)> {
+    let mut params: Vec<(String, String)> = params.iter()
+        .map(|(key, value)| (key.as_ref().to_string(), value.clone()))
+        .collect();
+    for prefix in prefixes {
+        for (key, value) in request.metadata.iter().flatten() {
+            params.push((format!("{}[{}]", prefix, key), value.clone()));
         }
     }
+    params
 }
 
-#[async_trait]
+fn metadata_of(object: &Value) -> Option<HashMap<String, String>> {
+    object["metadata"].as_object()
+        .filter(|metadata| !metadata.is_empty())
+        .map(|metadata| {
+            metadata.iter()
+                .filter_map(|(key, value)| value.as_str().map(|value| (key.clone(), value.to_string())))
+                .collect()
+        })
+}
+
+/// Expandable fields hold either an ID or the expanded object.
+fn id_of(value: &Value) -> Option<String> {
+    value.as_str().or_else(|| value["id"].as_str()).map(String::from)
+}
+
+fn charge_details(charge: &Value) -> ChargeDetails {
+    let details = &charge["payment_method_details"];
+    let method_type = details["type"].as_str();
+    let method = method_type.map(|method_type| &details[method_type]).unwrap_or(&Value::Null);
+    ChargeDetails {
+        charge_id: charge["id"].as_str().map(String::from),
+        status: charge["status"].as_str().map(String::from),
+        amount: charge["amount"].as_i64(),
+        captured: charge["captured"].as_bool(),
+        refunded: charge["refunded"].as_bool(),
+        amount_refunded: charge["amount_refunded"].as_i64(),
+        failure_code: charge["failure_code"].as_str().map(String::from),
+        failure_message: charge["failure_message"].as_str().map(String::from),
+        receipt_url: charge["receipt_url"].as_str().map(String::from),
+        payment_method: method_type.map(|method_type| PaymentMethodDetails {
+            method_type: Some(method_type.to_string()),
+            brand: method["brand"].as_str().map(String::from),
+            last4: method["last4"].as_str().map(String::from),
+            exp_month: method["exp_month"].as_i64(),
+            exp_year: method["exp_year"].as_i64(),
+            country: method["country"].as_str().map(String::from),
+            wallet: method["wallet"]["type"].as_str().map(String::from),
+        }),
+    }
+}
+
+/// A Stripe object type that can be listed, keyed by its LIST request type.
+struct ListableObject {
+    request_type: &'static str,
+    name: &'static str,
+    path: &'static str,
+    /// Search API endpoint, for types Stripe can search.
+    search_path: Option<&'static str>,
+    filters_customer: bool,
+    filters_status: bool,
+}
+
+const LISTABLE_OBJECTS: &[ListableObject] = &[
+    ListableObject {
+        request_type: "LIST_CHARGES",
+        name: "charge",
+        path: "/charges",
+        search_path: Some("/charges/search"),
+        filters_customer: true,
+        filters_status: false,
+    },
+    ListableObject {
+        request_type: "LIST_PAYMENT_INTENTS",
+        name: "payment_intent",
+        path: "/payment_intents",
+        search_path: Some("/payment_intents/search"),
+        filters_customer: true,
+        filters_status: false,
+    },
+    ListableObject {
+        request_type: "LIST_REFUNDS",
+        name: "refund",
+        path: "/refunds",
+        search_path: None,
+        filters_customer: false,
+        filters_status: false,
+    },
+    ListableObject {
+        request_type: "LIST_CHECKOUT_SESSIONS",
+        name: "checkout_session",
+        path: "/checkout/sessions",
+        search_path: None,
+        filters_customer: true,
+        filters_status: true,
+    },
+    ListableObject {
+        request_type: "LIST_CUSTOMERS",
+        name: "customer",
+        path: "/customers",
+        search_path: Some("/customers/search"),
+        filters_customer: false,
+        filters_status: false,
+    },
+];
+
+const DEFAULT_LIST_LIMIT: i64 = 10;
+const MAX_LIST_LIMIT: i64 = 100;
+
+fn list_limit(request: &PaymentRequest) -> String {
+    request.limit.unwrap_or(DEFAULT_LIST_LIMIT).clamp(1, MAX_LIST_LIMIT).to_string()
+}
+
+/// Page size, creation window and cursor accepted by every Stripe list endpoint.
+fn list_page_params(request: &PaymentRequest) -> Vec<(&'static str, String)> {
+    let mut params = vec![("limit", list_limit(request))];
+    if let Some(created_gte) = request.created_gte {
+        params.push(("created[gte]", created_gte.to_string()));
+    }
+    if let Some(created_lte) = request.created_lte {
+        params.push(("created[lte]", created_lte.to_string()));
+    }
+    if let Some(starting_after) = &request.starting_after {
+        params.push(("starting_after", starting_after.clone()));
+    }
+    params
+}
+
+/// The `startingAfter` for the next page: the ID of the page's last object, while Stripe has more.
+fn next_cursor(data: &[Value], has_more: bool) -> Option<String> {
+    data.last().and_then(|last| last["id"].as_str()).map(String::from).filter(|_| has_more)
+}
+
+pub struct StripeListProcessor {
+    client: StripeClient,
+}
+
+impl StripeListProcessor {
+    pub fn new(client: StripeClient) -> Self {
+        StripeListProcessor {
+            client,
+        }
+    }
+
+    /// Builds list parameters from the request's filters, rejecting those Stripe does not
+    /// support for the object type (a search `query` can express them instead).
+    fn list_params(target: &ListableObject, request: &PaymentRequest) -> Result<Vec<(&'static str, String)>, GatewayError> {
+        let mut params = list_page_params(request);
+        if let Some(customer_id) = &request.customer_id {
+            if !target.filters_customer {
+                return Err(GatewayError::InvalidRequest(format!("Cannot filter {} by customer", target.name)));
+            }
+            params.push(("customer", customer_id.clone()));
+        }
+        if let Some(status) = &request.status {
+            if !target.filters_status {
+                return Err(GatewayError::InvalidRequest(format!("Cannot filter {} by status", target.name)));
+            }
+            params.push(("status", status.clone()));
+        }
+        Ok(params)
+    }
+}
+
+#[async_trait]
+impl ListProcessor for StripeListProcessor {
+    async fn process_list(&self, request: &PaymentRequest) -> Result<ListResponse, GatewayError> {
+        let request_type = request.request_type.to_uppercase();
+        let target = LISTABLE_OBJECTS.iter()
+            .find(|object| object.request_type == request_type)
+            .ok_or_else(|| GatewayError::InvalidRequest(format!("Invalid request type: {}", request.request_type)))?;
+        tracing::info!("Listing {} for store: {}", target.name, request.store_id);
+
+        // Lists page by object ID (`startingAfter`), searches by an opaque token (`page`).
+        // Queries often name customers' emails, so they are never logged.
+        let (body, next_page) = match &request.query {
+            Some(query) => {
+                let search_path = target.search_path
+                    .ok_or_else(|| GatewayError::InvalidRequest(format!("Stripe cannot search {}", target.name)))?;
+                if request.created_gte.is_some() || request.created_lte.is_some()
+                    || request.customer_id.is_some() || request.status.is_some()
+                {
+                    return Err(GatewayError::InvalidRequest("Filters cannot be combined with a search query".to_string()));
+                }
+                if request.starting_after.is_some() {
+                    return Err(GatewayError::InvalidRequest("Search results are paged with page, not startingAfter".to_string()));
+                }
+                let mut params = vec![("query", query.clone()), ("limit", list_limit(request))];
+                if let Some(page) = &request.page {
+                    params.push(("page", page.clone()));
+                }
+                let body = self.client.get_with_params(search_path, &params).await?;
+                let next_page = body["next_page"].as_str().map(String::from);
+                (body, next_page)
+            }
+            None => {
+                if request.page.is_some() {
+                    return Err(GatewayError::InvalidRequest("Lists are paged with startingAfter; page is for search queries".to_string()));
+                }
+                let params = Self::list_params(target, request)?;
+                (self.client.get_with_params(target.path, &params).await?, None)
+            }
+        };
+
+        let data = body["data"].as_array().cloned().unwrap_or_default();
+        let has_more = body["has_more"].as_bool().unwrap_or(false);
+
+        Ok(ListResponse {
+            status: "success".to_string(),
+            message: None,
+            object_type: target.name.to_string(),
+            items: data.iter().map(list_item).collect(),
+            has_more,
+            next_cursor: next_cursor(&data, has_more).filter(|_| request.query.is_none()),
+            next_page: next_page.filter(|_| has_more),
+            status_code: 200,
+        })
+    }
+}
+
+fn list_item(object: &Value) -> ListItem {
+    ListItem {
+        id: object["id"].as_str().map(String::from),
+        status: object["status"].as_str().map(String::from),
+        amount: object["amount"].as_i64().or_else(|| object["amount_total"].as_i64()),
+        currency: object["currency"].as_str().map(String::from),
+        customer_id: id_of(&object["customer"]),
+        created: object["created"].as_i64(),
+        metadata: metadata_of(object),
+    }
+}
+
+const DEFAULT_WEBHOOK_DEDUP_TTL_SECS: i64 = 7 * 24 * 60 * 60;
+
+pub struct StripeWebhookProcessor {
+    _client: StripeClient,
+    events: Arc<dyn WebhookEventStore>,
+    router: Arc<EventRouter>,
+}
+
+impl StripeWebhookProcessor {
+    pub fn new(client: StripeClient, events: Arc<dyn WebhookEventStore>, router: Arc<EventRouter>) -> Self {
+        StripeWebhookProcessor {
+            _client: client,
+            events,
+            router,
+        }
+    }
+
+    fn dedup_ttl_secs() -> i64 {
+        std::env::var("WEBHOOK_DEDUP_TTL_SECS")
+            .ok()
+            .and_then(|ttl| ttl.parse().ok())
+            .unwrap_or(DEFAULT_WEBHOOK_DEDUP_TTL_SECS)
+    }
+
+    /// Moves the event's object to its new status unless a newer event got there first.
+    async fn apply_object_state(&self, store_id: &str, event_id: &str, event: &HashMap<String, Value>) -> Result<bool, GatewayError> {
+        let object = event.get("data").map(|data| &data["object"]);
+        let (Some(object_id), Some(status)) = (
+            object.and_then(|o| o["id"].as_str()),
+            object.and_then(|o| o["status"].as_str()),
+        ) else {
+            return Ok(true);
+        };
+        let state = ObjectState {
+            object_id: object_id.to_string(),
+            status: status.to_string(),
+            event_id: event_id.to_string(),
+            event_created: event.get("created").and_then(|v| v.as_i64()).unwrap_or(0),
+        };
+        self.events.advance_object_state(store_id, &state).await
+    }
+}
+
+#[async_trait]
 impl WebhookProcessor for StripeWebhookProcessor {
     async fn process_webhook(&self, request: &PaymentRequest) -> Result<WebhookResponse, GatewayError> {
-        log::info!("Processing webhook for store: {}", request.store_id);
+        tracing::info!("Processing webhook for store: {}", request.store_id);
         if request.webhook_event.is_none() {
             return Err(GatewayError::InvalidRequest("Webhook event data required".to_string()));
         }
@@ -282,13 +1340,1662 @@ impl WebhookProcessor for StripeWebhookProcessor {
             .and_then(|v| v.as_str())
             .ok_or_else(|| GatewayError::InvalidRequest("Webhook event type required".to_string()))?;
 
-        log::debug!("Received webhook event: id={}, type={}", event_id, event_type);
+        tracing::debug!("Received webhook event: id={}, type={}", event_id, event_type);
 
-        Ok(WebhookResponse {
+        let mut response = WebhookResponse {
             status: "success".to_string(),
             message: None,
             event_id: Some(event_id.to_string()),
+            event_type: Some(event_type.to_string()),
+            duplicate: false,
+            stale: false,
+            dispute: None,
+            invoice: None,
+            status_code: 200,
+        };
+
+        if !self.events.claim_event(&request.store_id, event_id, Self::dedup_ttl_secs()).await? {
+            tracing::info!("Ignoring duplicate webhook event: {}", event_id);
+            response.message = Some("Duplicate event ignored".to_string());
+            response.duplicate = true;
+            return Ok(response);
+        }
+
+        let handled = async {
+            let applied = self.apply_object_state(&request.store_id, event_id, event).await?;
+            let envelope = EventEnvelope::from_event(&request.store_id, event, !applied);
+            let published = self.router.dispatch(&envelope).await?;
+            Ok::<(bool, usize), GatewayError>((applied, published))
+        }.await;
+
+        match handled {
+            Ok((applied, published)) => {
+                tracing::info!(published, "Forwarded webhook event: {}", event_id);
+                if event_type.starts_with("charge.dispute.") {
+                    if let Some(data) = event.get("data") {
+                        let dispute = dispute_details(&data["object"]);
+                        tracing::info!(dispute_status = ?dispute.dispute_status, "Dispute event: {}", event_type);
+                        response.dispute = Some(dispute);
+                    }
+                }
+                if event_type.starts_with("invoice.") {
+                    if let Some(data) = event.get("data") {
+                        let invoice = invoice_details(&data["object"]);
+                        tracing::info!(invoice_status = ?invoice.invoice_status, "Invoice event: {}", event_type);
+                        response.invoice = Some(invoice);
+                    }
+                }
+                if !applied {
+                    tracing::info!("Webhook event is stale: {}", event_id);
+                    metrics::count("StaleWebhookEvents", &[("EventType", event_type)]);
+                    response.message = Some("Stale event ignored".to_string());
+                    response.stale = true;
+                }
+            }
+            Err(e) => {
+                // Let Stripe's redelivery try again rather than losing the event.
+                if let Err(release_error) = self.events.release_event(&request.store_id, event_id).await {
+                    tracing::error!("Failed to release webhook event {}: {}", event_id, release_error);
+                }
+                return Err(e);
+            }
+        }
+
+        Ok(response)
+    }
+}
+
+pub struct StripeDisputeProcessor {
+    client: StripeClient,
+}
+
+impl StripeDisputeProcessor {
+    pub fn new(client: StripeClient) -> Self {
+        StripeDisputeProcessor {
+            client,
+        }
+    }
+
+    fn dispute_id(request: &PaymentRequest) -> Result<&str, GatewayError> {
+        request.dispute_id.as_deref()
+            .filter(|id| !id.is_empty())
+            .ok_or_else(|| GatewayError::InvalidRequest("Dispute ID is required".to_string()))
+    }
+
+    fn single(dispute: &Value, message: Option<String>) -> DisputeResponse {
+        DisputeResponse {
+            status: "success".to_string(),
+            message,
+            disputes: vec![dispute_details(dispute)],
+            has_more: false,
+            next_cursor: None,
+            status_code: 200,
+        }
+    }
+}
+
+#[async_trait]
+impl DisputeProcessor for StripeDisputeProcessor {
+    async fn list_disputes(&self, request: &PaymentRequest) -> Result<DisputeResponse, GatewayError> {
+        tracing::info!("Listing disputes for store: {}", request.store_id);
+        let mut params = list_page_params(request);
+        if let Some(charge_id) = &request.charge_id {
+            params.push(("charge", charge_id.clone()));
+        }
+        if let Some(payment_intent_id) = &request.payment_intent_id {
+            params.push(("payment_intent", payment_intent_id.clone()));
+        }
+
+        let body = self.client.get_with_params("/disputes", &params).await?;
+        let data = body["data"].as_array().cloned().unwrap_or_default();
+        let has_more = body["has_more"].as_bool().unwrap_or(false);
+
+        Ok(DisputeResponse {
+            status: "success".to_string(),
+            message: None,
+            disputes: data.iter().map(dispute_details).collect(),
+            has_more,
+            next_cursor: next_cursor(&data, has_more),
+            status_code: 200,
+        })
+    }
+
+    async fn retrieve_dispute(&self, request: &PaymentRequest) -> Result<DisputeResponse, GatewayError> {
+        let dispute_id = Self::dispute_id(request)?;
+        tracing::info!("Retrieving dispute: {}", dispute_id);
+        let body = self.client.get(&format!("/disputes/{}", encode(dispute_id))).await?;
+        Ok(Self::single(&body, None))
+    }
+
+    /// Uploads any evidence files, then updates the dispute with them and the text evidence.
+    /// Evidence is only staged unless `submit` is set.
+    async fn submit_evidence(&self, request: &PaymentRequest) -> Result<DisputeResponse, GatewayError> {
+        let dispute_id = Self::dispute_id(request)?;
+        tracing::info!("Updating evidence for dispute: {}", dispute_id);
+        if request.evidence.is_none() && request.evidence_files.is_none() {
+            return Err(GatewayError::InvalidRequest("Evidence or evidence files are required".to_string()));
+        }
+
+        let mut params: Vec<(String, String)> = Vec::new();
+        for (field, text) in request.evidence.iter().flatten() {
+            params.push((format!("evidence[{}]", field), text.clone()));
+        }
+        for file in request.evidence_files.iter().flatten() {
+            let content = base64::engine::general_purpose::STANDARD.decode(&file.content)
+                .map_err(|e| GatewayError::InvalidRequest(format!("Evidence file {} is not valid base64: {}", file.file_name, e)))?;
+            let uploaded = self.client.upload_file("dispute_evidence", &file.file_name, &file.content_type, &content).await?;
+            let file_id = uploaded["id"].as_str()
+                .ok_or_else(|| GatewayError::Unexpected("Stripe returned no file ID".to_string()))?;
+            params.push((format!("evidence[{}]", file.field), file_id.to_string()));
+        }
+        let submit = request.submit.unwrap_or(false);
+        params.push(("submit".to_string(), submit.to_string()));
+
+        let body = self.client.post(&format!("/disputes/{}", encode(dispute_id)), &params).await?;
+        let message = if submit { "Evidence submitted" } else { "Evidence staged" };
+        Ok(Self::single(&body, Some(message.to_string())))
+    }
+
+    /// Accepts the dispute, conceding it to the cardholder.
+    async fn close_dispute(&self, request: &PaymentRequest) -> Result<DisputeResponse, GatewayError> {
+        let dispute_id = Self::dispute_id(request)?;
+        tracing::info!("Closing dispute: {}", dispute_id);
+        let body = self.client.post::<&str, &str>(&format!("/disputes/{}/close", encode(dispute_id)), &[]).await?;
+        Ok(Self::single(&body, None))
+    }
+}
+
+fn dispute_details(dispute: &Value) -> DisputeDetails {
+    let evidence_details = &dispute["evidence_details"];
+    DisputeDetails {
+        dispute_id: dispute["id"].as_str().map(String::from),
+        charge_id: id_of(&dispute["charge"]),
+        payment_intent_id: id_of(&dispute["payment_intent"]),
+        amount: dispute["amount"].as_i64(),
+        currency: dispute["currency"].as_str().map(String::from),
+        reason: dispute["reason"].as_str().map(String::from),
+        dispute_status: dispute["status"].as_str().map(String::from),
+        evidence_due_by: evidence_details["due_by"].as_i64(),
+        has_evidence: evidence_details["has_evidence"].as_bool(),
+        past_due: evidence_details["past_due"].as_bool(),
+        submission_count: evidence_details["submission_count"].as_i64(),
+        is_charge_refundable: dispute["is_charge_refundable"].as_bool(),
+        created: dispute["created"].as_i64(),
+    }
+}
+
+/// Bills customers with invoices: drafts are built from invoice items, then finalized and
+/// sent for the customer to pay on the hosted page, or paid, voided or written off here.
+pub struct StripeInvoiceProcessor {
+    client: StripeClient,
+}
+
+const DEFAULT_DAYS_UNTIL_DUE: i64 = 30;
+
+impl StripeInvoiceProcessor {
+    pub fn new(client: StripeClient) -> Self {
+        StripeInvoiceProcessor {
+            client,
+        }
+    }
+
+    fn invoice_id(request: &PaymentRequest) -> Result<&str, GatewayError> {
+        request.invoice_id.as_deref()
+            .filter(|id| !id.is_empty())
+            .ok_or_else(|| GatewayError::InvalidRequest("Invoice ID is required".to_string()))
+    }
+
+    /// POSTs to one of the invoice's state transitions, e.g. `finalize` or `void`.
+    async fn transition(&self, request: &PaymentRequest, action: &str, params: &[(&str, String)]) -> Result<InvoiceResponse, GatewayError> {
+        let invoice_id = Self::invoice_id(request)?;
+        tracing::info!("Invoice {}: {}", action, invoice_id);
+        let body = self.client.post(&format!("/invoices/{}/{}", encode(invoice_id), action), params).await?;
+        Ok(Self::single(&body))
+    }
+
+    fn single(invoice: &Value) -> InvoiceResponse {
+        InvoiceResponse {
+            status: "success".to_string(),
+            message: None,
+            invoice: invoice_details(invoice),
+            status_code: 200,
+        }
+    }
+}
+
+#[async_trait]
+impl InvoiceProcessor for StripeInvoiceProcessor {
+    /// Creates a draft invoice and adds the request's items to it, deleting the draft again if
+    /// an item cannot be added. Drafts are not finalized automatically, so they can be reviewed
+    /// before FINALIZE_INVOICE or SEND_INVOICE.
+    async fn create_invoice(&self, request: &PaymentRequest) -> Result<InvoiceResponse, GatewayError> {
+        tracing::info!("Creating invoice for store: {}", request.store_id);
+        let customer_id = request.customer_id.as_deref()
+            .ok_or_else(|| GatewayError::InvalidRequest("Customer ID is required".to_string()))?;
+        let items = request.invoice_items.as_deref()
+            .filter(|items| !items.is_empty())
+            .ok_or_else(|| GatewayError::InvalidRequest("At least one invoice item is required".to_string()))?;
+        // Built up front so an invalid item fails the request before a draft is created.
+        let mut item_params = Vec::new();
+        for item in items {
+            let mut params = vec![("customer", customer_id.to_string())];
+            match (&item.price_id, item.amount, &request.currency) {
+                (Some(price_id), _, _) => {
+                    params.push(("price", price_id.clone()));
+                    params.push(("quantity", item.quantity.unwrap_or(1).to_string()));
+                }
+                (None, Some(amount), Some(currency)) => {
+                    params.push(("amount", amount.to_string()));
+                    params.push(("currency", currency.clone()));
+                }
+                _ => return Err(GatewayError::InvalidRequest("Invoice items need a price ID, or an amount and the request currency".to_string())),
+            }
+            if let Some(description) = &item.description {
+                params.push(("description", description.clone()));
+            }
+            item_params.push(params);
+        }
+
+        let collection_method = request.collection_method.as_deref().unwrap_or("send_invoice");
+        let mut params = vec![
+            ("customer", customer_id.to_string()),
+            ("collection_method", collection_method.to_string()),
+            ("auto_advance", "false".to_string()),
+            ("pending_invoice_items_behavior", "exclude".to_string()),
+        ];
+        match collection_method {
+            "send_invoice" => params.push(("days_until_due", request.days_until_due.unwrap_or(DEFAULT_DAYS_UNTIL_DUE).to_string())),
+            "charge_automatically" => {}
+            other => return Err(GatewayError::InvalidRequest(format!("Unsupported collection method: {}", other))),
+        }
+        if let Some(currency) = &request.currency {
+            params.push(("currency", currency.clone()));
+        }
+        if let Some(description) = &request.description {
+            params.push(("description", description.clone()));
+        }
+        let invoice = self.client.post("/invoices", &with_metadata(&params, &["metadata"], request)).await?;
+        let invoice_id = invoice["id"].as_str()
+            .ok_or_else(|| GatewayError::Unexpected("Stripe returned an invoice without an ID".to_string()))?;
+
+        for mut params in item_params {
+            params.push(("invoice", invoice_id.to_string()));
+            if let Err(error) = self.client.post("/invoiceitems", &with_metadata(&params, &["metadata"], request)).await {
+                // Don't leave a partial draft behind; deleting it also drops the items already added.
+                if let Err(cleanup_error) = self.client.delete(&format!("/invoices/{}", encode(invoice_id))).await {
+                    tracing::error!("Failed to delete partial draft invoice {}: {}", invoice_id, cleanup_error);
+                }
+                return Err(error);
+            }
+        }
+
+        // Re-read the draft so the response carries the added lines and totals.
+        let body = self.client.get(&format!("/invoices/{}", encode(invoice_id))).await?;
+        Ok(Self::single(&body))
+    }
+
+    async fn retrieve_invoice(&self, request: &PaymentRequest) -> Result<InvoiceResponse, GatewayError> {
+        let invoice_id = Self::invoice_id(request)?;
+        tracing::info!("Retrieving invoice: {}", invoice_id);
+        let body = self.client.get(&format!("/invoices/{}", encode(invoice_id))).await?;
+        Ok(Self::single(&body))
+    }
+
+    async fn finalize_invoice(&self, request: &PaymentRequest) -> Result<InvoiceResponse, GatewayError> {
+        self.transition(request, "finalize", &[("auto_advance", "false".to_string())]).await
+    }
+
+    /// Emails a finalized invoice (finalizing a draft first) to the customer.
+    async fn send_invoice(&self, request: &PaymentRequest) -> Result<InvoiceResponse, GatewayError> {
+        self.transition(request, "send", &[]).await
+    }
+
+    async fn void_invoice(&self, request: &PaymentRequest) -> Result<InvoiceResponse, GatewayError> {
+        self.transition(request, "void", &[]).await
+    }
+
+    async fn mark_uncollectible(&self, request: &PaymentRequest) -> Result<InvoiceResponse, GatewayError> {
+        self.transition(request, "mark_uncollectible", &[]).await
+    }
+
+    /// Pays an open invoice with the customer's default or the given saved payment method.
+    async fn pay_invoice(&self, request: &PaymentRequest) -> Result<InvoiceResponse, GatewayError> {
+        let mut params = Vec::new();
+        if let Some(payment_method_id) = &request.payment_method_id {
+            params.push(("payment_method", payment_method_id.clone()));
+        }
+        if let Some(off_session) = request.off_session {
+            params.push(("off_session", off_session.to_string()));
+        }
+        self.transition(request, "pay", &params).await
+    }
+}
+
+fn invoice_details(invoice: &Value) -> InvoiceDetails {
+    let lines = invoice["lines"]["data"].as_array().cloned().unwrap_or_default();
+    InvoiceDetails {
+        invoice_id: invoice["id"].as_str().map(String::from),
+        customer_id: id_of(&invoice["customer"]),
+        invoice_status: invoice["status"].as_str().map(String::from),
+        number: invoice["number"].as_str().map(String::from),
+        currency: invoice["currency"].as_str().map(String::from),
+        total: invoice["total"].as_i64(),
+        amount_due: invoice["amount_due"].as_i64(),
+        amount_paid: invoice["amount_paid"].as_i64(),
+        amount_remaining: invoice["amount_remaining"].as_i64(),
+        collection_method: invoice["collection_method"].as_str().map(String::from),
+        due_date: invoice["due_date"].as_i64(),
+        hosted_invoice_url: invoice["hosted_invoice_url"].as_str().map(String::from),
+        invoice_pdf: invoice["invoice_pdf"].as_str().map(String::from),
+        lines: lines.iter().map(|line| InvoiceLine {
+            id: line["id"].as_str().map(String::from),
+            description: line["description"].as_str().map(String::from),
+            amount: line["amount"].as_i64(),
+            currency: line["currency"].as_str().map(String::from),
+            quantity: line["quantity"].as_i64(),
+            // Newer API versions moved the price under `pricing`.
+            price_id: id_of(&line["price"]).or_else(|| id_of(&line["pricing"]["price_details"]["price"])),
+        }).collect(),
+        created: invoice["created"].as_i64(),
+        metadata: metadata_of(invoice),
+    }
+}
+
+/// Opens Stripe Customer Portal sessions, where customers manage their own payment methods,
+/// subscriptions and invoices.
+pub struct StripeBillingPortalProcessor {
+    client: StripeClient,
+    /// The store's portal configuration, if it has its own.
+    configuration: Option<String>,
+}
+
+impl StripeBillingPortalProcessor {
+    pub fn new(client: StripeClient, configuration: Option<String>) -> Self {
+        StripeBillingPortalProcessor {
+            client,
+            configuration,
+        }
+    }
+}
+
+#[async_trait]
+impl BillingPortalProcessor for StripeBillingPortalProcessor {
+    async fn create_portal_session(&self, request: &PaymentRequest) -> Result<BillingPortalResponse, GatewayError> {
+        tracing::info!("Creating billing portal session for store: {}", request.store_id);
+        let customer_id = request.customer_id.as_deref()
+            .ok_or_else(|| GatewayError::InvalidRequest("Customer ID is required".to_string()))?;
+        let return_url = request.return_url.as_deref()
+            .ok_or_else(|| GatewayError::InvalidRequest("Return URL is required".to_string()))?;
+
+        let mut params = vec![
+            ("customer", customer_id.to_string()),
+            ("return_url", return_url.to_string()),
+        ];
+        if let Some(configuration) = request.portal_configuration_id.as_ref().or(self.configuration.as_ref()) {
+            params.push(("configuration", configuration.clone()));
+        }
+        if let Some(locale) = &request.locale {
+            params.push(("locale", locale.clone()));
+        }
+
+        let body = self.client.post("/billing_portal/sessions", &params).await?;
+
+        Ok(BillingPortalResponse {
+            status: "success".to_string(),
+            message: None,
+            portal_session_id: body["id"].as_str().map(String::from),
+            portal_url: body["url"].as_str().map(String::from),
+            customer_id: id_of(&body["customer"]),
+            configuration_id: id_of(&body["configuration"]),
+            return_url: body["return_url"].as_str().map(String::from),
             status_code: 200,
         })
     }
-}
\ No newline at end of file
+}
+
+/// The coupon a request applies, and the promotion code it was redeemed through, if any.
+struct Discount {
+    coupon: Value,
+    promotion_code: Option<Value>,
+}
+
+impl Discount {
+    fn promotion_code_id(&self) -> Option<String> {
+        self.promotion_code.as_ref().and_then(|code| code["id"].as_str()).map(String::from)
+    }
+}
+
+/// Looks up the request's `couponId` or `promotionCode`, rejecting ones that can no longer
+/// be redeemed, or that are restricted to another customer.
+async fn resolve_discount(client: &StripeClient, request: &PaymentRequest) -> Result<Option<Discount>, GatewayError> {
+    let coupon_path = |coupon_id: &str| format!("/coupons/{}", encode(coupon_id));
+    let discount = match (&request.coupon_id, &request.promotion_code) {
+        (None, None) => return Ok(None),
+        (Some(_), Some(_)) => return Err(GatewayError::InvalidRequest("Apply either a coupon or a promotion code, not both".to_string())),
+        (Some(coupon_id), None) => Discount {
+            coupon: client.get(&coupon_path(coupon_id)).await?,
+            promotion_code: None,
+        },
+        (None, Some(code)) => {
+            let params = [("code", code.as_str()), ("active", "true"), ("limit", "1")];
+            let body = client.get_with_params("/promotion_codes", &params).await?;
+            let promotion_code = body["data"].get(0)
+                .ok_or_else(|| GatewayError::InvalidRequest(format!("Unknown or inactive promotion code: {}", code)))?;
+            check_promotion_code(promotion_code, request, now_secs())?;
+            // Newer API versions return the coupon's ID under `promotion` instead of the coupon.
+            let coupon = match &promotion_code["coupon"] {
+                Value::Object(_) => promotion_code["coupon"].clone(),
+                _ => {
+                    let coupon_id = id_of(&promotion_code["coupon"])
+                        .or_else(|| id_of(&promotion_code["promotion"]["coupon"]))
+                        .ok_or_else(|| GatewayError::Unexpected("Promotion code has no coupon".to_string()))?;
+                    client.get(&coupon_path(&coupon_id)).await?
+                }
+            };
+            Discount {
+                coupon,
+                promotion_code: Some(promotion_code.clone()),
+            }
+        }
+    };
+    if !coupon_usable(&discount.coupon) {
+        return Err(GatewayError::InvalidRequest("Coupon is no longer valid".to_string()));
+    }
+    Ok(Some(discount))
+}
+
+/// Rejects a promotion code that has expired by `now`, or that belongs to another customer.
+fn check_promotion_code(promotion_code: &Value, request: &PaymentRequest, now: i64) -> Result<(), GatewayError> {
+    let code = promotion_code["code"].as_str().unwrap_or_default();
+    if promotion_code["expires_at"].as_i64().is_some_and(|expires_at| expires_at <= now) {
+        return Err(GatewayError::InvalidRequest(format!("Promotion code has expired: {}", code)));
+    }
+    if let Some(customer_id) = id_of(&promotion_code["customer"]) {
+        if request.customer_id.as_ref() != Some(&customer_id) {
+            return Err(GatewayError::InvalidRequest(format!("Promotion code is restricted to another customer: {}", code)));
+        }
+    }
+    Ok(())
+}
+
+/// Coupon metadata key set by DEACTIVATE_COUPON.
+const COUPON_DEACTIVATED_KEY: &str = "gateway_deactivated";
+
+/// Whether new discounts may use the coupon: Stripe still considers it valid, and it has
+/// not been deactivated through the gateway.
+fn coupon_usable(coupon: &Value) -> bool {
+    coupon["valid"].as_bool() == Some(true) && coupon["metadata"][COUPON_DEACTIVATED_KEY].as_str() != Some("true")
+}
+
+/// Manages the coupons and promotion codes CHARGE and PAYMENT_LINK requests can apply.
+pub struct StripeDiscountProcessor {
+    client: StripeClient,
+}
+
+impl StripeDiscountProcessor {
+    pub fn new(client: StripeClient) -> Self {
+        StripeDiscountProcessor {
+            client,
+        }
+    }
+
+    fn coupons(coupons: &[Value], has_more: bool, message: Option<String>) -> CouponResponse {
+        CouponResponse {
+            status: "success".to_string(),
+            message,
+            coupons: coupons.iter().map(coupon_details).collect(),
+            has_more,
+            next_cursor: next_cursor(coupons, has_more),
+            status_code: 200,
+        }
+    }
+
+    fn promotion_codes(promotion_codes: &[Value], has_more: bool) -> PromotionCodeResponse {
+        PromotionCodeResponse {
+            status: "success".to_string(),
+            message: None,
+            promotion_codes: promotion_codes.iter().map(promotion_code_details).collect(),
+            has_more,
+            next_cursor: next_cursor(promotion_codes, has_more),
+            status_code: 200,
+        }
+    }
+}
+
+#[async_trait]
+impl DiscountProcessor for StripeDiscountProcessor {
+    async fn create_coupon(&self, request: &PaymentRequest) -> Result<CouponResponse, GatewayError> {
+        tracing::info!("Creating coupon for store: {}", request.store_id);
+        let mut params = Vec::new();
+        match (request.percent_off, request.amount_off, &request.currency) {
+            (Some(percent_off), None, _) if percent_off > 0.0 && percent_off <= 100.0 => {
+                params.push(("percent_off", percent_off.to_string()));
+            }
+            (None, Some(amount_off), Some(currency)) if amount_off > 0 => {
+                params.push(("amount_off", amount_off.to_string()));
+                params.push(("currency", currency.clone()));
+            }
+            _ => return Err(GatewayError::InvalidRequest(
+                "Coupons need either a percent off between 0 and 100, or an amount off and currency".to_string()
+            )),
+        }
+        let duration = request.duration.as_deref().unwrap_or("once");
+        match (duration, request.duration_in_months) {
+            ("repeating", Some(months)) => {
+                params.push(("duration", duration.to_string()));
+                params.push(("duration_in_months", months.to_string()));
+            }
+            ("repeating", None) => return Err(GatewayError::InvalidRequest("Duration in months is required for repeating coupons".to_string())),
+            ("once" | "forever", _) => params.push(("duration", duration.to_string())),
+            (other, _) => return Err(GatewayError::InvalidRequest(format!("Unsupported duration: {}", other))),
+        }
+        if let Some(coupon_id) = &request.coupon_id {
+            params.push(("id", coupon_id.clone()));
+        }
+        if let Some(name) = &request.name {
+            params.push(("name", name.clone()));
+        }
+        if let Some(max_redemptions) = request.max_redemptions {
+            params.push(("max_redemptions", max_redemptions.to_string()));
+        }
+        if let Some(redeem_by) = request.redeem_by {
+            params.push(("redeem_by", redeem_by.to_string()));
+        }
+
+        let body = self.client.post("/coupons", &with_metadata(&params, &["metadata"], request)).await?;
+        Ok(Self::coupons(&[body], false, None))
+    }
+
+    async fn list_coupons(&self, request: &PaymentRequest) -> Result<CouponResponse, GatewayError> {
+        tracing::info!("Listing coupons for store: {}", request.store_id);
+        let body = self.client.get_with_params("/coupons", &list_page_params(request)).await?;
+        let data = body["data"].as_array().cloned().unwrap_or_default();
+        Ok(Self::coupons(&data, body["has_more"].as_bool().unwrap_or(false), None))
+    }
+
+    /// Stripe can only delete coupons, not switch them off, so the coupon is flagged in its
+    /// metadata, which CHARGE and PAYMENT_LINK then refuse, and its promotion codes are
+    /// deactivated. Discounts already applied with it carry on.
+    async fn deactivate_coupon(&self, request: &PaymentRequest) -> Result<CouponResponse, GatewayError> {
+        let coupon_id = request.coupon_id.as_deref()
+            .filter(|id| !id.is_empty())
+            .ok_or_else(|| GatewayError::InvalidRequest("Coupon ID is required".to_string()))?;
+        tracing::info!("Deactivating coupon: {}", coupon_id);
+        let metadata_key = format!("metadata[{}]", COUPON_DEACTIVATED_KEY);
+        let coupon = self.client.post(&format!("/coupons/{}", encode(coupon_id)), &[(metadata_key.as_str(), "true")]).await?;
+
+        let mut deactivated = 0;
+        let mut starting_after: Option<String> = None;
+        loop {
+            let mut params = vec![
+                ("coupon", coupon_id.to_string()),
+                ("active", "true".to_string()),
+                ("limit", MAX_LIST_LIMIT.to_string()),
+            ];
+            if let Some(starting_after) = &starting_after {
+                params.push(("starting_after", starting_after.clone()));
+            }
+            let body = self.client.get_with_params("/promotion_codes", &params).await?;
+            let data = body["data"].as_array().cloned().unwrap_or_default();
+            for promotion_code in data.iter().filter_map(|code| code["id"].as_str()) {
+                self.client.post(&format!("/promotion_codes/{}", encode(promotion_code)), &[("active", "false")]).await?;
+                deactivated += 1;
+            }
+            starting_after = next_cursor(&data, body["has_more"].as_bool().unwrap_or(false));
+            if starting_after.is_none() {
+                break;
+            }
+        }
+        Ok(Self::coupons(&[coupon], false, Some(format!("Coupon deactivated with {} promotion codes", deactivated))))
+    }
+
+    async fn create_promotion_code(&self, request: &PaymentRequest) -> Result<PromotionCodeResponse, GatewayError> {
+        tracing::info!("Creating promotion code for store: {}", request.store_id);
+        let coupon_id = request.coupon_id.as_deref()
+            .ok_or_else(|| GatewayError::InvalidRequest("Coupon ID is required".to_string()))?;
+
+        let mut params = vec![("coupon", coupon_id.to_string())];
+        if let Some(code) = &request.promotion_code {
+            params.push(("code", code.clone()));
+        }
+        if let Some(customer_id) = &request.customer_id {
+            params.push(("customer", customer_id.clone()));
+        }
+        if let Some(max_redemptions) = request.max_redemptions {
+            params.push(("max_redemptions", max_redemptions.to_string()));
+        }
+        if let Some(expires_at) = request.expires_at {
+            params.push(("expires_at", expires_at.to_string()));
+        }
+
+        let body = self.client.post("/promotion_codes", &with_metadata(&params, &["metadata"], request)).await?;
+        Ok(Self::promotion_codes(&[body], false))
+    }
+
+    async fn list_promotion_codes(&self, request: &PaymentRequest) -> Result<PromotionCodeResponse, GatewayError> {
+        tracing::info!("Listing promotion codes for store: {}", request.store_id);
+        let mut params = list_page_params(request);
+        if let Some(coupon_id) = &request.coupon_id {
+            params.push(("coupon", coupon_id.clone()));
+        }
+        if let Some(code) = &request.promotion_code {
+            params.push(("code", code.clone()));
+        }
+        if let Some(customer_id) = &request.customer_id {
+            params.push(("customer", customer_id.clone()));
+        }
+        if let Some(active) = request.active {
+            params.push(("active", active.to_string()));
+        }
+        let body = self.client.get_with_params("/promotion_codes", &params).await?;
+        let data = body["data"].as_array().cloned().unwrap_or_default();
+        Ok(Self::promotion_codes(&data, body["has_more"].as_bool().unwrap_or(false)))
+    }
+
+    async fn deactivate_promotion_code(&self, request: &PaymentRequest) -> Result<PromotionCodeResponse, GatewayError> {
+        let promotion_code_id = request.promotion_code_id.as_deref()
+            .filter(|id| !id.is_empty())
+            .ok_or_else(|| GatewayError::InvalidRequest("Promotion code ID is required".to_string()))?;
+        tracing::info!("Deactivating promotion code: {}", promotion_code_id);
+        let body = self.client.post(&format!("/promotion_codes/{}", encode(promotion_code_id)), &[("active", "false")]).await?;
+        Ok(Self::promotion_codes(&[body], false))
+    }
+}
+
+fn coupon_details(coupon: &Value) -> CouponDetails {
+    CouponDetails {
+        coupon_id: coupon["id"].as_str().map(String::from),
+        name: coupon["name"].as_str().map(String::from),
+        percent_off: coupon["percent_off"].as_f64(),
+        amount_off: coupon["amount_off"].as_i64(),
+        currency: coupon["currency"].as_str().map(String::from),
+        duration: coupon["duration"].as_str().map(String::from),
+        duration_in_months: coupon["duration_in_months"].as_i64(),
+        max_redemptions: coupon["max_redemptions"].as_i64(),
+        times_redeemed: coupon["times_redeemed"].as_i64(),
+        redeem_by: coupon["redeem_by"].as_i64(),
+        valid: Some(coupon_usable(coupon)),
+        created: coupon["created"].as_i64(),
+        metadata: metadata_of(coupon),
+    }
+}
+
+fn promotion_code_details(promotion_code: &Value) -> PromotionCodeDetails {
+    PromotionCodeDetails {
+        promotion_code_id: promotion_code["id"].as_str().map(String::from),
+        code: promotion_code["code"].as_str().map(String::from),
+        coupon_id: id_of(&promotion_code["coupon"]).or_else(|| id_of(&promotion_code["promotion"]["coupon"])),
+        active: promotion_code["active"].as_bool(),
+        customer_id: id_of(&promotion_code["customer"]),
+        max_redemptions: promotion_code["max_redemptions"].as_i64(),
+        times_redeemed: promotion_code["times_redeemed"].as_i64(),
+        expires_at: promotion_code["expires_at"].as_i64(),
+        created: promotion_code["created"].as_i64(),
+        metadata: metadata_of(promotion_code),
+    }
+}
+
+/// Reports balances, balance transactions and payouts. The client is expected to carry the
+/// connected account, if any, so these describe whichever account the request is for.
+pub struct StripeBalanceProcessor {
+    client: StripeClient,
+}
+
+impl StripeBalanceProcessor {
+    pub fn new(client: StripeClient) -> Self {
+        StripeBalanceProcessor {
+            client,
+        }
+    }
+
+    /// Lists balance transactions, only those settled by `payout_id` when given.
+    async fn transactions(&self, request: &PaymentRequest, payout_id: Option<&str>) -> Result<BalanceTransactionResponse, GatewayError> {
+        let mut params = list_page_params(request);
+        if let Some(payout_id) = payout_id {
+            params.push(("payout", payout_id.to_string()));
+        }
+        if let Some(transaction_type) = &request.transaction_type {
+            params.push(("type", transaction_type.clone()));
+        }
+        if let Some(currency) = &request.currency {
+            params.push(("currency", currency.clone()));
+        }
+        let body = self.client.get_with_params("/balance_transactions", &params).await?;
+        let data = body["data"].as_array().cloned().unwrap_or_default();
+        let has_more = body["has_more"].as_bool().unwrap_or(false);
+
+        Ok(BalanceTransactionResponse {
+            status: "success".to_string(),
+            message: None,
+            transactions: data.iter().map(balance_transaction_details).collect(),
+            has_more,
+            next_cursor: next_cursor(&data, has_more),
+            status_code: 200,
+        })
+    }
+}
+
+#[async_trait]
+impl BalanceProcessor for StripeBalanceProcessor {
+    async fn retrieve_balance(&self, request: &PaymentRequest) -> Result<BalanceResponse, GatewayError> {
+        tracing::info!("Retrieving balance for store: {}", request.store_id);
+        let body = self.client.get("/balance").await?;
+        let amounts = |key: &str| -> Vec<BalanceAmount> {
+            body[key].as_array().map(|amounts| {
+                amounts.iter().map(|amount| BalanceAmount {
+                    amount: amount["amount"].as_i64(),
+                    currency: amount["currency"].as_str().map(String::from),
+                }).collect()
+            }).unwrap_or_default()
+        };
+
+        Ok(BalanceResponse {
+            status: "success".to_string(),
+            message: None,
+            account_id: request.account_id.clone().filter(|id| !id.is_empty()),
+            available: amounts("available"),
+            pending: amounts("pending"),
+            instant_available: amounts("instant_available"),
+            livemode: body["livemode"].as_bool(),
+            status_code: 200,
+        })
+    }
+
+    async fn list_balance_transactions(&self, request: &PaymentRequest) -> Result<BalanceTransactionResponse, GatewayError> {
+        tracing::info!("Listing balance transactions for store: {}", request.store_id);
+        self.transactions(request, request.payout_id.as_deref()).await
+    }
+
+    async fn list_payouts(&self, request: &PaymentRequest) -> Result<PayoutResponse, GatewayError> {
+        tracing::info!("Listing payouts for store: {}", request.store_id);
+        let mut params = list_page_params(request);
+        if let Some(status) = &request.status {
+            params.push(("status", status.clone()));
+        }
+        let body = self.client.get_with_params("/payouts", &params).await?;
+        let data = body["data"].as_array().cloned().unwrap_or_default();
+        let has_more = body["has_more"].as_bool().unwrap_or(false);
+
+        Ok(PayoutResponse {
+            status: "success".to_string(),
+            message: None,
+            payouts: data.iter().map(payout_details).collect(),
+            transactions: None,
+            has_more,
+            next_cursor: next_cursor(&data, has_more),
+            status_code: 200,
+        })
+    }
+
+    /// Retrieves a payout with the balance transactions it settled. Stripe only tracks
+    /// these for automatic payouts, so manual ones come back without transactions.
+    async fn retrieve_payout(&self, request: &PaymentRequest) -> Result<PayoutResponse, GatewayError> {
+        let payout_id = request.payout_id.as_deref()
+            .filter(|id| !id.is_empty())
+            .ok_or_else(|| GatewayError::InvalidRequest("Payout ID is required".to_string()))?;
+        tracing::info!("Retrieving payout: {}", payout_id);
+        let payout = self.client.get(&format!("/payouts/{}", encode(payout_id))).await?;
+
+        let (transactions, has_more, next_cursor) = if payout["automatic"].as_bool() == Some(true) {
+            let page = self.transactions(request, Some(payout_id)).await?;
+            (Some(page.transactions), page.has_more, page.next_cursor)
+        } else {
+            (None, false, None)
+        };
+
+        Ok(PayoutResponse {
+            status: "success".to_string(),
+            message: None,
+            payouts: vec![payout_details(&payout)],
+            transactions,
+            has_more,
+            next_cursor,
+            status_code: 200,
+        })
+    }
+}
+
+fn balance_transaction_details(transaction: &Value) -> BalanceTransactionDetails {
+    BalanceTransactionDetails {
+        balance_transaction_id: transaction["id"].as_str().map(String::from),
+        transaction_type: transaction["type"].as_str().map(String::from),
+        reporting_category: transaction["reporting_category"].as_str().map(String::from),
+        amount: transaction["amount"].as_i64(),
+        fee: transaction["fee"].as_i64(),
+        net: transaction["net"].as_i64(),
+        currency: transaction["currency"].as_str().map(String::from),
+        transaction_status: transaction["status"].as_str().map(String::from),
+        available_on: transaction["available_on"].as_i64(),
+        source_id: id_of(&transaction["source"]),
+        description: transaction["description"].as_str().map(String::from),
+        fee_details: transaction["fee_details"].as_array().map(|fees| {
+            fees.iter().map(|fee| FeeDetail {
+                fee_type: fee["type"].as_str().map(String::from),
+                amount: fee["amount"].as_i64(),
+                currency: fee["currency"].as_str().map(String::from),
+                description: fee["description"].as_str().map(String::from),
+            }).collect()
+        }).unwrap_or_default(),
+        created: transaction["created"].as_i64(),
+    }
+}
+
+fn payout_details(payout: &Value) -> PayoutDetails {
+    PayoutDetails {
+        payout_id: payout["id"].as_str().map(String::from),
+        amount: payout["amount"].as_i64(),
+        currency: payout["currency"].as_str().map(String::from),
+        payout_status: payout["status"].as_str().map(String::from),
+        arrival_date: payout["arrival_date"].as_i64(),
+        method: payout["method"].as_str().map(String::from),
+        automatic: payout["automatic"].as_bool(),
+        destination_id: id_of(&payout["destination"]),
+        balance_transaction_id: id_of(&payout["balance_transaction"]),
+        failure_code: payout["failure_code"].as_str().map(String::from),
+        failure_message: payout["failure_message"].as_str().map(String::from),
+        description: payout["description"].as_str().map(String::from),
+        created: payout["created"].as_i64(),
+        metadata: metadata_of(payout),
+    }
+}
+
+/// Manages the store's Products and Prices, so checkout can sell catalog prices by ID.
+/// Neither can be deleted once used, so they are archived instead.
+pub struct StripeCatalogProcessor {
+    client: StripeClient,
+}
+
+/// Tiers and currency options are only returned when expanded.
+const PRICE_EXPANSIONS: [(&str, &str); 2] = [("expand[]", "tiers"), ("expand[]", "currency_options")];
+
+impl StripeCatalogProcessor {
+    pub fn new(client: StripeClient) -> Self {
+        StripeCatalogProcessor {
+            client,
+        }
+    }
+
+    fn product_id(request: &PaymentRequest) -> Result<&str, GatewayError> {
+        request.product_id.as_deref()
+            .filter(|id| !id.is_empty())
+            .ok_or_else(|| GatewayError::InvalidRequest("Product ID is required".to_string()))
+    }
+
+    fn price_id(request: &PaymentRequest) -> Result<&str, GatewayError> {
+        request.price_id.as_deref()
+            .filter(|id| !id.is_empty())
+            .ok_or_else(|| GatewayError::InvalidRequest("Price ID is required".to_string()))
+    }
+
+    fn product_params(request: &PaymentRequest) -> Vec<(String, String)> {
+        let mut params = Vec::new();
+        if let Some(name) = &request.name {
+            params.push(("name".to_string(), name.clone()));
+        }
+        if let Some(description) = &request.description {
+            params.push(("description".to_string(), description.clone()));
+        }
+        if let Some(active) = request.active {
+            params.push(("active".to_string(), active.to_string()));
+        }
+        with_metadata(&params, &["metadata"], request)
+    }
+
+    /// The fields Stripe allows to change after a price is created.
+    fn price_update_params(request: &PaymentRequest) -> Vec<(String, String)> {
+        let mut params: Vec<(String, String)> = PRICE_EXPANSIONS.iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect();
+        if let Some(active) = request.active {
+            params.push(("active".to_string(), active.to_string()));
+        }
+        if let Some(nickname) = &request.nickname {
+            params.push(("nickname".to_string(), nickname.clone()));
+        }
+        if let Some(lookup_key) = &request.lookup_key {
+            params.push(("lookup_key".to_string(), lookup_key.clone()));
+            params.push(("transfer_lookup_key".to_string(), "true".to_string()));
+        }
+        with_metadata(&params, &["metadata"], request)
+    }
+
+    fn list_params(request: &PaymentRequest) -> Vec<(&'static str, String)> {
+        let mut params = list_page_params(request);
+        if let Some(active) = request.active {
+            params.push(("active", active.to_string()));
+        }
+        params
+    }
+
+    fn products(body: &Value) -> ProductResponse {
+        let data = body["data"].as_array().cloned().unwrap_or_default();
+        let has_more = body["has_more"].as_bool().unwrap_or(false);
+        ProductResponse {
+            status: "success".to_string(),
+            message: None,
+            products: data.iter().map(product_details).collect(),
+            has_more,
+            next_cursor: next_cursor(&data, has_more),
+            status_code: 200,
+        }
+    }
+
+    fn single_product(product: &Value) -> ProductResponse {
+        ProductResponse {
+            status: "success".to_string(),
+            message: None,
+            products: vec![product_details(product)],
+            has_more: false,
+            next_cursor: None,
+            status_code: 200,
+        }
+    }
+
+    fn prices(body: &Value) -> PriceResponse {
+        let data = body["data"].as_array().cloned().unwrap_or_default();
+        let has_more = body["has_more"].as_bool().unwrap_or(false);
+        PriceResponse {
+            status: "success".to_string(),
+            message: None,
+            prices: data.iter().map(price_details).collect(),
+            has_more,
+            next_cursor: next_cursor(&data, has_more),
+            status_code: 200,
+        }
+    }
+
+    fn single_price(price: &Value) -> PriceResponse {
+        PriceResponse {
+            status: "success".to_string(),
+            message: None,
+            prices: vec![price_details(price)],
+            has_more: false,
+            next_cursor: None,
+            status_code: 200,
+        }
+    }
+}
+
+#[async_trait]
+impl CatalogProcessor for StripeCatalogProcessor {
+    async fn create_product(&self, request: &PaymentRequest) -> Result<ProductResponse, GatewayError> {
+        tracing::info!("Creating product for store: {}", request.store_id);
+        if request.name.as_deref().unwrap_or("").is_empty() {
+            return Err(GatewayError::InvalidRequest("Product name is required".to_string()));
+        }
+        let body = self.client.post("/products", &Self::product_params(request)).await?;
+        Ok(Self::single_product(&body))
+    }
+
+    async fn update_product(&self, request: &PaymentRequest) -> Result<ProductResponse, GatewayError> {
+        let product_id = Self::product_id(request)?;
+        tracing::info!("Updating product: {}", product_id);
+        let body = self.client.post(&format!("/products/{}", encode(product_id)), &Self::product_params(request)).await?;
+        Ok(Self::single_product(&body))
+    }
+
+    async fn archive_product(&self, request: &PaymentRequest) -> Result<ProductResponse, GatewayError> {
+        let product_id = Self::product_id(request)?;
+        tracing::info!("Archiving product: {}", product_id);
+        let body = self.client.post(&format!("/products/{}", encode(product_id)), &[("active", "false")]).await?;
+        Ok(Self::single_product(&body))
+    }
+
+    async fn list_products(&self, request: &PaymentRequest) -> Result<ProductResponse, GatewayError> {
+        tracing::info!("Listing products for store: {}", request.store_id);
+        let body = self.client.get_with_params("/products", &Self::list_params(request)).await?;
+        Ok(Self::products(&body))
+    }
+
+    /// Creates a one-time or recurring price, charged per unit or in tiers, optionally with
+    /// unit amounts in further currencies.
+    async fn create_price(&self, request: &PaymentRequest) -> Result<PriceResponse, GatewayError> {
+        tracing::info!("Creating price for store: {}", request.store_id);
+        let currency = request.currency.as_deref()
+            .ok_or_else(|| GatewayError::InvalidRequest("Currency is required".to_string()))?;
+
+        let mut params: Vec<(String, String)> = PRICE_EXPANSIONS.iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect();
+        params.push(("currency".to_string(), currency.to_string()));
+        match (&request.product_id, &request.name) {
+            (Some(product_id), _) => params.push(("product".to_string(), product_id.clone())),
+            (None, Some(name)) => params.push(("product_data[name]".to_string(), name.clone())),
+            (None, None) => return Err(GatewayError::InvalidRequest("Product ID or name is required".to_string())),
+        }
+        match (&request.tiers, request.amount) {
+            (Some(tiers), _) => {
+                let tiers_mode = request.tiers_mode.as_deref().unwrap_or("");
+                if tiers_mode != "graduated" && tiers_mode != "volume" {
+                    return Err(GatewayError::InvalidRequest("Tiers mode must be graduated or volume".to_string()));
+                }
+                params.push(("billing_scheme".to_string(), "tiered".to_string()));
+                params.push(("tiers_mode".to_string(), tiers_mode.to_string()));
+                for (index, tier) in tiers.iter().enumerate() {
+                    let up_to = tier.up_to.map(|up_to| up_to.to_string()).unwrap_or_else(|| "inf".to_string());
+                    params.push((format!("tiers[{}][up_to]", index), up_to));
+                    if let Some(unit_amount) = tier.unit_amount {
+                        params.push((format!("tiers[{}][unit_amount]", index), unit_amount.to_string()));
+                    }
+                    if let Some(flat_amount) = tier.flat_amount {
+                        params.push((format!("tiers[{}][flat_amount]", index), flat_amount.to_string()));
+                    }
+                }
+            }
+            (None, Some(amount)) => params.push(("unit_amount".to_string(), amount.to_string())),
+            (None, None) => return Err(GatewayError::InvalidRequest("Amount or tiers are required".to_string())),
+        }
+        if let Some(recurring) = &request.recurring {
+            params.push(("recurring[interval]".to_string(), recurring.interval.clone()));
+            if let Some(interval_count) = recurring.interval_count {
+                params.push(("recurring[interval_count]".to_string(), interval_count.to_string()));
+            }
+            if let Some(usage_type) = &recurring.usage_type {
+                params.push(("recurring[usage_type]".to_string(), usage_type.clone()));
+            }
+        }
+        for (option_currency, unit_amount) in request.currency_options.iter().flatten() {
+            params.push((format!("currency_options[{}][unit_amount]", option_currency.to_lowercase()), unit_amount.to_string()));
+        }
+        if let Some(lookup_key) = &request.lookup_key {
+            params.push(("lookup_key".to_string(), lookup_key.clone()));
+        }
+        if let Some(nickname) = &request.nickname {
+            params.push(("nickname".to_string(), nickname.clone()));
+        }
+
+        let body = self.client.post("/prices", &with_metadata(&params, &["metadata"], request)).await?;
+        Ok(Self::single_price(&body))
+    }
+
+    async fn update_price(&self, request: &PaymentRequest) -> Result<PriceResponse, GatewayError> {
+        let price_id = Self::price_id(request)?;
+        tracing::info!("Updating price: {}", price_id);
+        let body = self.client.post(&format!("/prices/{}", encode(price_id)), &Self::price_update_params(request)).await?;
+        Ok(Self::single_price(&body))
+    }
+
+    async fn archive_price(&self, request: &PaymentRequest) -> Result<PriceResponse, GatewayError> {
+        let price_id = Self::price_id(request)?;
+        tracing::info!("Archiving price: {}", price_id);
+        let mut params = PRICE_EXPANSIONS.to_vec();
+        params.push(("active", "false"));
+        let body = self.client.post(&format!("/prices/{}", encode(price_id)), &params).await?;
+        Ok(Self::single_price(&body))
+    }
+
+    async fn list_prices(&self, request: &PaymentRequest) -> Result<PriceResponse, GatewayError> {
+        tracing::info!("Listing prices for store: {}", request.store_id);
+        let mut params = Self::list_params(request);
+        params.push(("expand[]", "data.tiers".to_string()));
+        params.push(("expand[]", "data.currency_options".to_string()));
+        if let Some(product_id) = &request.product_id {
+            params.push(("product", product_id.clone()));
+        }
+        if let Some(currency) = &request.currency {
+            params.push(("currency", currency.clone()));
+        }
+        if let Some(lookup_key) = &request.lookup_key {
+            params.push(("lookup_keys[]", lookup_key.clone()));
+        }
+        let body = self.client.get_with_params("/prices", &params).await?;
+        Ok(Self::prices(&body))
+    }
+}
+
+fn product_details(product: &Value) -> ProductDetails {
+    ProductDetails {
+        product_id: product["id"].as_str().map(String::from),
+        name: product["name"].as_str().map(String::from),
+        description: product["description"].as_str().map(String::from),
+        active: product["active"].as_bool(),
+        default_price_id: id_of(&product["default_price"]),
+        created: product["created"].as_i64(),
+        updated: product["updated"].as_i64(),
+        metadata: metadata_of(product),
+    }
+}
+
+fn price_details(price: &Value) -> PriceDetails {
+    let recurring = &price["recurring"];
+    PriceDetails {
+        price_id: price["id"].as_str().map(String::from),
+        product_id: id_of(&price["product"]),
+        active: price["active"].as_bool(),
+        price_type: price["type"].as_str().map(String::from),
+        currency: price["currency"].as_str().map(String::from),
+        unit_amount: price["unit_amount"].as_i64(),
+        recurring: recurring["interval"].as_str().map(|interval| RecurringPrice {
+            interval: interval.to_string(),
+            interval_count: recurring["interval_count"].as_i64(),
+            usage_type: recurring["usage_type"].as_str().map(String::from),
+        }),
+        billing_scheme: price["billing_scheme"].as_str().map(String::from),
+        tiers_mode: price["tiers_mode"].as_str().map(String::from),
+        tiers: price["tiers"].as_array().map(|tiers| {
+            tiers.iter().map(|tier| PriceTier {
+                up_to: tier["up_to"].as_i64(),
+                unit_amount: tier["unit_amount"].as_i64(),
+                flat_amount: tier["flat_amount"].as_i64(),
+            }).collect()
+        }),
+        currency_options: price["currency_options"].as_object().map(|options| {
+            options.iter()
+                .filter_map(|(currency, option)| option["unit_amount"].as_i64().map(|amount| (currency.clone(), amount)))
+                .collect()
+        }),
+        lookup_key: price["lookup_key"].as_str().map(String::from),
+        nickname: price["nickname"].as_str().map(String::from),
+        created: price["created"].as_i64(),
+        metadata: metadata_of(price),
+    }
+}
+
+pub struct StripeAccountProcessor {
+    client: StripeClient,
+}
+
+impl StripeAccountProcessor {
+    pub fn new(client: StripeClient) -> Self {
+        StripeAccountProcessor {
+            client,
+        }
+    }
+
+    fn required_account_id(request: &PaymentRequest) -> Result<&str, GatewayError> {
+        request.account_id.as_deref()
+            .filter(|id| !id.is_empty())
+            .ok_or_else(|| GatewayError::InvalidRequest("Account ID is required".to_string()))
+    }
+
+    fn string_list(value: &Value) -> Vec<String> {
+        value.as_array()
+            .map(|items| items.iter().filter_map(|v| v.as_str().map(String::from)).collect())
+            .unwrap_or_default()
+    }
+
+    fn account_response(body: &Value) -> AccountResponse {
+        let capabilities = body["capabilities"].as_object().map(|caps| {
+            caps.iter()
+                .filter_map(|(name, state)| state.as_str().map(|s| (name.clone(), s.to_string())))
+                .collect::<HashMap<String, String>>()
+        });
+        let requirements = body["requirements"].as_object().map(|_| AccountRequirements {
+            currently_due: Self::string_list(&body["requirements"]["currently_due"]),
+            eventually_due: Self::string_list(&body["requirements"]["eventually_due"]),
+            past_due: Self::string_list(&body["requirements"]["past_due"]),
+            disabled_reason: body["requirements"]["disabled_reason"].as_str().map(String::from),
+            current_deadline: body["requirements"]["current_deadline"].as_i64(),
+        });
+
+        AccountResponse {
+            status: "success".to_string(),
+            message: None,
+            account_id: body["id"].as_str().map(String::from),
+            account_type: body["type"].as_str().map(String::from),
+            charges_enabled: body["charges_enabled"].as_bool(),
+            payouts_enabled: body["payouts_enabled"].as_bool(),
+            details_submitted: body["details_submitted"].as_bool(),
+            capabilities,
+            requirements,
+            metadata: metadata_of(body),
+            status_code: 200,
+        }
+    }
+}
+
+#[async_trait]
+impl AccountProcessor for StripeAccountProcessor {
+    async fn create_account(&self, request: &PaymentRequest) -> Result<AccountResponse, GatewayError> {
+        tracing::info!("Creating connected account for store: {}", request.store_id);
+        let account_type = request.account_type.as_deref().unwrap_or("express").to_lowercase();
+        if account_type != "express" && account_type != "standard" {
+            return Err(GatewayError::InvalidRequest(format!("Unsupported account type: {}", account_type)));
+        }
+
+        let mut params = vec![("type".to_string(), account_type.clone())];
+        if let Some(country) = &request.country {
+            params.push(("country".to_string(), country.clone()));
+        }
+        if let Some(email) = &request.email {
+            params.push(("email".to_string(), email.clone()));
+        }
+        if let Some(business_type) = &request.business_type {
+            params.push(("business_type".to_string(), business_type.clone()));
+        }
+
+        // Express accounts cannot be created without requesting at least one capability.
+        let capabilities = match &request.capabilities {
+            Some(capabilities) => capabilities.clone(),
+            None if account_type == "express" => vec!["card_payments".to_string(), "transfers".to_string()],
+            None => Vec::new(),
+        };
+        for capability in capabilities {
+            params.push((format!("capabilities[{}][requested]", capability), "true".to_string()));
+        }
+
+        let body = self.client.post("/accounts", &with_metadata(&params, &["metadata"], request)).await?;
+
+        Ok(Self::account_response(&body))
+    }
+
+    async fn create_account_link(&self, request: &PaymentRequest) -> Result<AccountLinkResponse, GatewayError> {
+        tracing::info!("Creating account onboarding link for store: {}", request.store_id);
+        let account_id = Self::required_account_id(request)?;
+        if request.refresh_url.is_none() || request.return_url.is_none() {
+            return Err(GatewayError::InvalidRequest("Refresh and return URLs are required".to_string()));
+        }
+
+        let params = [
+            ("account", account_id.to_string()),
+            ("refresh_url", request.refresh_url.as_deref().unwrap_or("").to_string()),
+            ("return_url", request.return_url.as_deref().unwrap_or("").to_string()),
+            ("type", "account_onboarding".to_string()),
+        ];
+
+        let body = self.client.post("/account_links", &params).await?;
+
+        Ok(AccountLinkResponse {
+            status: "success".to_string(),
+            message: None,
+            account_id: Some(account_id.to_string()),
+            url: body["url"].as_str().map(String::from),
+            expires_at: body["expires_at"].as_i64(),
+            status_code: 200,
+        })
+    }
+
+    async fn retrieve_account(&self, request: &PaymentRequest) -> Result<AccountResponse, GatewayError> {
+        tracing::info!("Retrieving connected account status for store: {}", request.store_id);
+        let account_id = Self::required_account_id(request)?;
+
+        let body = self.client.get(&format!("/accounts/{}", encode(account_id))).await?;
+
+        Ok(Self::account_response(&body))
+    }
+
+    async fn create_login_link(&self, request: &PaymentRequest) -> Result<AccountLinkResponse, GatewayError> {
+        tracing::info!("Creating dashboard login link for store: {}", request.store_id);
+        let account_id = Self::required_account_id(request)?;
+
+        let body = self.client.post::<&str, &str>(&format!("/accounts/{}/login_links", encode(account_id)), &[]).await?;
+
+        Ok(AccountLinkResponse {
+            status: "success".to_string(),
+            message: None,
+            account_id: Some(account_id.to_string()),
+            url: body["url"].as_str().map(String::from),
+            expires_at: None,
+            status_code: 200,
+        })
+    }
+}
+
+const DEFAULT_HISTORY_LIMIT: i64 = 25;
+const MAX_HISTORY_LIMIT: i64 = 100;
+
+pub struct LedgerHistoryProcessor {
+    ledger: Arc<dyn TransactionStore>,
+}
+
+impl LedgerHistoryProcessor {
+    pub fn new(ledger: Arc<dyn TransactionStore>) -> Self {
+        LedgerHistoryProcessor { ledger }
+    }
+}
+
+#[async_trait]
+impl HistoryProcessor for LedgerHistoryProcessor {
+    async fn process_history(&self, request: &PaymentRequest) -> Result<TransactionHistoryResponse, GatewayError> {
+        tracing::info!("Processing transaction history for store: {}", request.store_id);
+        let transactions = match &request.object_id {
+            Some(object_id) => self.ledger.get(&request.store_id, object_id).await?
+                .into_iter()
+                .collect(),
+            None => {
+                let limit = request.limit.unwrap_or(DEFAULT_HISTORY_LIMIT).clamp(1, MAX_HISTORY_LIMIT);
+                self.ledger.list(&request.store_id, limit as usize).await?
+            }
+        };
+
+        Ok(TransactionHistoryResponse {
+            status: "success".to_string(),
+            message: None,
+            transactions,
+            status_code: 200,
+        })
+    }
+}
+
+pub struct MerchantWebhookDeliveryProcessor {
+    notifier: MerchantNotifier,
+    deliveries: Arc<dyn DeliveryStore>,
+}
+
+impl MerchantWebhookDeliveryProcessor {
+    pub fn new(notifier: MerchantNotifier, deliveries: Arc<dyn DeliveryStore>) -> Self {
+        MerchantWebhookDeliveryProcessor { notifier, deliveries }
+    }
+}
+
+#[async_trait]
+impl DeliveryProcessor for MerchantWebhookDeliveryProcessor {
+    async fn list_deliveries(&self, request: &PaymentRequest) -> Result<WebhookDeliveriesResponse, GatewayError> {
+        tracing::info!("Listing webhook deliveries for store: {}", request.store_id);
+        let deliveries = match &request.delivery_id {
+            Some(delivery_id) => self.deliveries.get_delivery(&request.store_id, delivery_id).await?
+                .into_iter()
+                .collect(),
+            None => {
+                let limit = request.limit.unwrap_or(DEFAULT_HISTORY_LIMIT).clamp(1, MAX_HISTORY_LIMIT);
+                self.deliveries.list_deliveries(&request.store_id, limit as usize).await?
+            }
+        };
+
+        Ok(WebhookDeliveriesResponse {
+            status: "success".to_string(),
+            message: None,
+            deliveries,
+            status_code: 200,
+        })
+    }
+
+    async fn replay_delivery(&self, request: &PaymentRequest) -> Result<WebhookDeliveriesResponse, GatewayError> {
+        let delivery_id = request.delivery_id.as_ref()
+            .ok_or_else(|| GatewayError::InvalidRequest("Delivery ID is required".to_string()))?;
+        tracing::info!("Replaying webhook delivery: {}", delivery_id);
+        let delivery = self.notifier.replay(&request.store_id, delivery_id).await?;
+
+        Ok(WebhookDeliveriesResponse {
+            status: "success".to_string(),
+            message: Some(format!("Delivery is {}", delivery.status)),
+            deliveries: vec![delivery],
+            status_code: 200,
+        })
+    }
+
+    async fn retry_deliveries(&self, request: &PaymentRequest) -> Result<WebhookDeliveriesResponse, GatewayError> {
+        tracing::info!("Retrying due webhook deliveries for store: {}", request.store_id);
+        let limit = request.limit.unwrap_or(DEFAULT_HISTORY_LIMIT).clamp(1, MAX_HISTORY_LIMIT);
+        let deliveries = self.notifier.retry_due(&request.store_id, limit as usize).await?;
+
+        Ok(WebhookDeliveriesResponse {
+            status: "success".to_string(),
+            message: Some(format!("Retried {} deliveries", deliveries.len())),
+            deliveries,
+            status_code: 200,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use super::*;
+    use crate::ledger::InMemoryTransactionStore;
+    use crate::redact::ApiKey;
+
+    fn charge_processor(redemptions: Arc<InMemoryTransactionStore>) -> StripeChargeProcessor {
+        StripeChargeProcessor::new(StripeClient::new(ApiKey::new("sk_test_unused".to_string())), redemptions)
+    }
+
+    fn applied(coupon_id: &str, promotion_code_id: Option<&str>) -> Option<AppliedDiscount> {
+        Some(AppliedDiscount {
+            coupon_id: Some(coupon_id.to_string()),
+            promotion_code_id: promotion_code_id.map(String::from),
+            original_amount: 1000,
+            amount_discounted: 100,
+        })
+    }
+
+    fn payment_intent(status: &str) -> Value {
+        json!({
+            "id": "pi_1",
+            "status": status,
+            "amount": 900,
+            "currency": "usd",
+            "metadata": { "coupon_id": "SUMMER", "promotion_code_id": "promo_1" },
+        })
+    }
+
+    fn request(fields: Value) -> PaymentRequest {
+        let mut body = json!({ "storeId": "store-a", "requestType": "LIST_CHARGES" });
+        body.as_object_mut().unwrap().extend(fields.as_object().unwrap().clone());
+        serde_json::from_value(body).unwrap()
+    }
+
+    fn list_processor() -> StripeListProcessor {
+        StripeListProcessor::new(StripeClient::new(ApiKey::new("sk_test_unused".to_string())))
+    }
+
+    async fn list_error(fields: Value) -> String {
+        match list_processor().process_list(&request(fields)).await {
+            Err(GatewayError::InvalidRequest(message)) => message,
+            other => panic!("expected an invalid request, got {:?}", other.map(|response| response.items.len())),
+        }
+    }
+
+    #[test]
+    fn list_page_params_clamp_the_limit_and_pass_the_cursor() {
+        let params = list_page_params(&request(json!({})));
+        assert_eq!(params, [("limit", "10".to_string())]);
+        assert_eq!(list_page_params(&request(json!({ "limit": 0 })))[0].1, "1");
+        assert_eq!(list_page_params(&request(json!({ "limit": 500 })))[0].1, "100");
+
+        let params = list_page_params(&request(json!({ "createdGte": 100, "createdLte": 200, "startingAfter": "ch_9" })));
+        assert_eq!(params, [
+            ("limit", "10".to_string()),
+            ("created[gte]", "100".to_string()),
+            ("created[lte]", "200".to_string()),
+            ("starting_after", "ch_9".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn next_cursor_is_the_last_id_while_there_are_more() {
+        let data = [json!({ "id": "ch_1" }), json!({ "id": "ch_2" })];
+        assert_eq!(next_cursor(&data, true).as_deref(), Some("ch_2"));
+        assert_eq!(next_cursor(&data, false), None);
+        assert_eq!(next_cursor(&[], true), None);
+    }
+
+    #[test]
+    fn list_params_reject_filters_the_object_does_not_support() {
+        let refunds = LISTABLE_OBJECTS.iter().find(|object| object.name == "refund").unwrap();
+        assert!(StripeListProcessor::list_params(refunds, &request(json!({ "customerId": "cus_1" }))).is_err());
+        let charges = LISTABLE_OBJECTS.iter().find(|object| object.name == "charge").unwrap();
+        assert!(StripeListProcessor::list_params(charges, &request(json!({ "status": "succeeded" }))).is_err());
+        let params = StripeListProcessor::list_params(charges, &request(json!({ "customerId": "cus_1" }))).unwrap();
+        assert!(params.contains(&("customer", "cus_1".to_string())));
+    }
+
+    #[tokio::test]
+    async fn lists_reject_unknown_objects() {
+        let message = list_error(json!({ "requestType": "LIST_PAYOUT_SCHEDULES" })).await;
+        assert!(message.contains("Invalid request type"), "{}", message);
+    }
+
+    #[tokio::test]
+    async fn search_rejects_objects_stripe_cannot_search() {
+        let message = list_error(json!({ "requestType": "LIST_REFUNDS", "query": "amount>100" })).await;
+        assert!(message.contains("cannot search refund"), "{}", message);
+    }
+
+    #[test]
+    fn search_queries_stay_out_of_request_logs() {
+        let search = request(json!({ "requestType": "LIST_CUSTOMERS", "query": "email:'jane@example.com'" }));
+        assert!(!format!("{:?}", search).contains("jane@example.com"));
+    }
+
+    #[tokio::test]
+    async fn search_and_list_paging_do_not_mix() {
+        let message = list_error(json!({ "query": "amount>100", "startingAfter": "ch_9" })).await;
+        assert!(message.contains("paged with page"), "{}", message);
+        let message = list_error(json!({ "page": "page_token" })).await;
+        assert!(message.contains("paged with startingAfter"), "{}", message);
+        let message = list_error(json!({ "query": "amount>100", "customerId": "cus_1" })).await;
+        assert!(message.contains("cannot be combined"), "{}", message);
+    }
+
+    fn status_target(fields: Value) -> Result<(&'static str, String), GatewayError> {
+        StripeStatusProcessor::target(&request(fields)).map(|(target, object_id)| (target.name, object_id))
+    }
+
+    #[test]
+    fn status_target_takes_object_id_or_the_legacy_ids_but_not_both() {
+        for legacy in [json!({ "chargeId": "ch_1" }), json!({ "sessionId": "cs_1" })] {
+            let mut fields = legacy.clone();
+            fields["objectId"] = json!("pi_1");
+            let Err(GatewayError::InvalidRequest(message)) = status_target(fields) else {
+                panic!("objectId was accepted alongside {}", legacy);
+            };
+            assert!(message.contains("not both"), "{}", message);
+        }
+        assert_eq!(status_target(json!({ "chargeId": "ch_1" })).unwrap(), ("charge", "ch_1".to_string()));
+        assert_eq!(status_target(json!({ "sessionId": "cs_1" })).unwrap(), ("checkout_session", "cs_1".to_string()));
+        assert_eq!(status_target(json!({ "chargeId": "ch_1", "sessionId": "cs_1" })).unwrap().0, "charge");
+    }
+
+    #[test]
+    fn status_target_infers_the_type_from_the_id_prefix() {
+        assert_eq!(status_target(json!({ "objectId": "pi_1" })).unwrap(), ("payment_intent", "pi_1".to_string()));
+        assert_eq!(status_target(json!({ "objectId": "pyr_1" })).unwrap().0, "refund");
+        assert_eq!(status_target(json!({ "objectType": "Dispute", "objectId": "x_1" })).unwrap().0, "dispute");
+        assert!(status_target(json!({ "objectId": "sub_1" })).is_err());
+        assert!(status_target(json!({ "objectType": "charge" })).is_err());
+        assert!(status_target(json!({})).is_err());
+    }
+
+    fn is_invalid<T>(result: Result<T, GatewayError>, expected: &str) -> bool {
+        matches!(result, Err(GatewayError::InvalidRequest(message)) if message.contains(expected))
+    }
+
+    #[test]
+    fn percent_off_is_rounded_to_the_nearest_unit() {
+        let coupon = json!({ "id": "SUMMER", "percent_off": 12.5 });
+        assert_eq!(discount_amount(&coupon, 1000, "usd").unwrap(), 125);
+        assert_eq!(discount_amount(&coupon, 999, "usd").unwrap(), 125);
+        assert_eq!(discount_amount(&json!({ "percent_off": 33.3 }), 100, "eur").unwrap(), 33);
+    }
+
+    #[test]
+    fn amount_off_applies_only_in_the_coupons_currency() {
+        let coupon = json!({ "id": "FIVE", "amount_off": 500, "currency": "usd" });
+        assert_eq!(discount_amount(&coupon, 1000, "USD").unwrap(), 500);
+        assert!(is_invalid(discount_amount(&coupon, 1000, "eur"), "not valid in this currency"));
+        assert!(is_invalid(discount_amount(&json!({ "id": "EMPTY" }), 1000, "usd"), "no discount"));
+    }
+
+    #[test]
+    fn a_discount_must_leave_something_to_charge() {
+        assert!(is_invalid(discount_amount(&json!({ "percent_off": 100.0 }), 1000, "usd"), "must be positive"));
+        let coupon = json!({ "amount_off": 1500, "currency": "usd" });
+        assert!(is_invalid(discount_amount(&coupon, 1000, "usd"), "must be positive"));
+        assert_eq!(discount_amount(&coupon, 1501, "usd").unwrap(), 1500);
+    }
+
+    #[test]
+    fn minimum_amount_is_checked_in_the_charge_currency() {
+        let code = json!({
+            "code": "SPRING",
+            "restrictions": {
+                "minimum_amount": 2000,
+                "minimum_amount_currency": "usd",
+                "currency_options": { "eur": { "minimum_amount": 1800 } },
+            },
+        });
+        assert!(check_minimum_amount(&code, 2000, "usd").is_ok());
+        assert!(is_invalid(check_minimum_amount(&code, 1999, "USD"), "minimum amount of 2000"));
+        assert!(check_minimum_amount(&code, 1800, "eur").is_ok());
+        assert!(is_invalid(check_minimum_amount(&code, 1799, "eur"), "minimum amount of 1800"));
+        assert!(is_invalid(check_minimum_amount(&code, 5000, "gbp"), "not valid in this currency"));
+        assert!(check_minimum_amount(&json!({ "code": "ANY", "restrictions": {} }), 1, "gbp").is_ok());
+    }
+
+    #[test]
+    fn promotion_codes_expire_and_can_be_tied_to_a_customer() {
+        let anyone = request(json!({}));
+        let code = json!({ "code": "SPRING", "expires_at": 1_000 });
+        assert!(check_promotion_code(&code, &anyone, 999).is_ok());
+        assert!(is_invalid(check_promotion_code(&code, &anyone, 1_000), "expired"));
+        assert!(check_promotion_code(&json!({ "code": "SPRING", "expires_at": null }), &anyone, 1_000).is_ok());
+
+        let code = json!({ "code": "VIP", "customer": "cus_1" });
+        assert!(check_promotion_code(&code, &request(json!({ "customerId": "cus_1" })), 0).is_ok());
+        assert!(is_invalid(check_promotion_code(&code, &request(json!({ "customerId": "cus_2" })), 0), "another customer"));
+        assert!(is_invalid(check_promotion_code(&code, &anyone, 0), "another customer"));
+    }
+
+    #[test]
+    fn deactivated_or_invalid_coupons_are_not_usable() {
+        assert!(coupon_usable(&json!({ "valid": true, "metadata": {} })));
+        assert!(coupon_usable(&json!({ "valid": true, "metadata": { COUPON_DEACTIVATED_KEY: "false" } })));
+        assert!(!coupon_usable(&json!({ "valid": false, "metadata": {} })));
+        assert!(!coupon_usable(&json!({ "valid": true, "metadata": { COUPON_DEACTIVATED_KEY: "true" } })));
+        assert!(!coupon_usable(&json!({})));
+    }
+
+    #[tokio::test]
+    async fn resolve_discount_needs_exactly_one_of_coupon_or_promotion_code() {
+        let client = StripeClient::new(ApiKey::new("sk_test_unused".to_string()));
+        assert!(resolve_discount(&client, &request(json!({}))).await.unwrap().is_none());
+        let both = request(json!({ "couponId": "SUMMER", "promotionCode": "SPRING" }));
+        assert!(is_invalid(resolve_discount(&client, &both).await, "not both"));
+    }
+
+    #[tokio::test]
+    async fn redemptions_stop_at_what_is_left_of_max_redemptions() {
+        let store = Arc::new(InMemoryTransactionStore::new());
+        let processor = charge_processor(store.clone());
+        let discount = Discount {
+            coupon: json!({ "id": "SUMMER", "max_redemptions": 3, "times_redeemed": 1 }),
+            promotion_code: Some(json!({ "id": "promo_1", "max_redemptions": 1 })),
+        };
+        let both_applied = applied("SUMMER", Some("promo_1")).unwrap();
+        let charge = request(json!({ "requestType": "CHARGE" }));
+
+        processor.claim_redemptions(&charge, &discount, &both_applied).await.unwrap();
+        // The promotion code is used up, and the coupon's claim is given back with it.
+        assert!(is_invalid(processor.claim_redemptions(&charge, &discount, &both_applied).await, "maximum redemptions: promotion_code:promo_1"));
+        let coupon_only = Discount { coupon: discount.coupon.clone(), promotion_code: None };
+        let coupon_applied = applied("SUMMER", None).unwrap();
+        processor.claim_redemptions(&charge, &coupon_only, &coupon_applied).await.unwrap();
+        assert!(is_invalid(processor.claim_redemptions(&charge, &coupon_only, &coupon_applied).await, "maximum redemptions: coupon:SUMMER"));
+    }
+
+    #[tokio::test]
+    async fn charge_awaiting_authentication_gives_its_redemptions_back_until_confirmed() {
+        let store = Arc::new(InMemoryTransactionStore::new());
+        let processor = charge_processor(store.clone());
+        let discount = applied("SUMMER", Some("promo_1"));
+        assert!(store.claim_redemption("store-a", "coupon:SUMMER", Some(1)).await.unwrap());
+        assert!(store.claim_redemption("store-a", "promotion_code:promo_1", Some(1)).await.unwrap());
+
+        let requires_action = Ok(off_session_response(&payment_intent("requires_action"), 402));
+        processor.settle_redemptions("store-a", &requires_action, &discount).await;
+        assert!(store.claim_redemption("store-a", "coupon:SUMMER", Some(1)).await.unwrap());
+        assert!(store.claim_redemption("store-a", "promotion_code:promo_1", Some(1)).await.unwrap());
+        store.release_redemption("store-a", "coupon:SUMMER").await.unwrap();
+        store.release_redemption("store-a", "promotion_code:promo_1").await.unwrap();
+
+        processor.confirm_redemptions("store-a", &payment_intent("succeeded")).await.unwrap();
+        assert!(!store.claim_redemption("store-a", "coupon:SUMMER", Some(1)).await.unwrap());
+        assert!(!store.claim_redemption("store-a", "promotion_code:promo_1", Some(1)).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn settled_charges_keep_their_redemptions_and_failed_ones_give_them_back() {
+        let store = Arc::new(InMemoryTransactionStore::new());
+        let processor = charge_processor(store.clone());
+        let discount = applied("SUMMER", None);
+        assert!(store.claim_redemption("store-a", "coupon:SUMMER", Some(1)).await.unwrap());
+
+        let succeeded = Ok(off_session_response(&payment_intent("succeeded"), 200));
+        processor.settle_redemptions("store-a", &succeeded, &discount).await;
+        assert!(!store.claim_redemption("store-a", "coupon:SUMMER", Some(1)).await.unwrap());
+
+        let failed = Err(GatewayError::InvalidRequest("Your card was declined.".to_string()));
+        processor.settle_redemptions("store-a", &failed, &discount).await;
+        assert!(store.claim_redemption("store-a", "coupon:SUMMER", Some(1)).await.unwrap());
+    }
+}
@@ -0,0 +1,312 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use async_trait::async_trait;
+use aws_sdk_eventbridge::types::PutEventsRequestEntry;
+use aws_sdk_sns::types::MessageAttributeValue as SnsAttribute;
+use aws_sdk_sqs::types::MessageAttributeValue as SqsAttribute;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::io::AsyncWriteExt;
+use crate::errors::GatewayError;
+use crate::ledger::now_secs;
+
+const ENVELOPE_VERSION: &str = "1";
+const DEFAULT_EVENT_SOURCE: &str = "stripe-gateway";
+
+/// The normalized form in which verified Stripe events are handed to downstream services.
+#[derive(Serialize, Debug, Clone)]
+pub struct EventEnvelope {
+    #[serde(rename = "envelopeVersion")]
+    pub envelope_version: String,
+    #[serde(rename = "eventId")]
+    pub event_id: String,
+    #[serde(rename = "eventType")]
+    pub event_type: String,
+    #[serde(rename = "storeId")]
+    pub store_id: String,
+    pub created: Option<i64>,
+    pub livemode: Option<bool>,
+    #[serde(rename = "objectId")]
+    pub object_id: Option<String>,
+    #[serde(rename = "objectType")]
+    pub object_type: Option<String>,
+    /// The event was older than the latest one already applied to the object.
+    pub stale: bool,
+    #[serde(rename = "receivedAt")]
+    pub received_at: i64,
+    pub data: Value,
+}
+
+impl EventEnvelope {
+    pub fn from_event(store_id: &str, event: &HashMap<String, Value>, stale: bool) -> Self {
+        let object = event.get("data").map(|data| data["object"].clone()).unwrap_or(Value::Null);
+        EventEnvelope {
+            envelope_version: ENVELOPE_VERSION.to_string(),
+            event_id: event.get("id").and_then(|v| v.as_str()).unwrap_or_default().to_string(),
+            event_type: event.get("type").and_then(|v| v.as_str()).unwrap_or_default().to_string(),
+            store_id: store_id.to_string(),
+            created: event.get("created").and_then(|v| v.as_i64()),
+            livemode: event.get("livemode").and_then(|v| v.as_bool()),
+            object_id: object["id"].as_str().map(String::from),
+            object_type: object["object"].as_str().map(String::from),
+            stale,
+            received_at: now_secs(),
+            data: object,
+        }
+    }
+}
+
+#[async_trait]
+pub trait EventPublisher: Send + Sync {
+    async fn publish(&self, envelope: &EventEnvelope) -> Result<(), GatewayError>;
+}
+
+pub struct SqsPublisher {
+    client: aws_sdk_sqs::Client,
+    queue_url: String,
+}
+
+#[async_trait]
+impl EventPublisher for SqsPublisher {
+    async fn publish(&self, envelope: &EventEnvelope) -> Result<(), GatewayError> {
+        let mut message = self.client.send_message()
+            .queue_url(&self.queue_url)
+            .message_body(serde_json::to_string(envelope)?)
+            .message_attributes("eventType", sqs_attribute(&envelope.event_type)?)
+            .message_attributes("storeId", sqs_attribute(&envelope.store_id)?);
+        if self.queue_url.ends_with(".fifo") {
+            message = message
+                .message_group_id(&envelope.store_id)
+                .message_deduplication_id(&envelope.event_id);
+        }
+        message.send()
+            .await
+            .map_err(|e| publish_error("SQS", aws_sdk_sqs::error::DisplayErrorContext(&e)))?;
+        Ok(())
+    }
+}
+
+pub struct SnsPublisher {
+    client: aws_sdk_sns::Client,
+    topic_arn: String,
+}
+
+#[async_trait]
+impl EventPublisher for SnsPublisher {
+    async fn publish(&self, envelope: &EventEnvelope) -> Result<(), GatewayError> {
+        let mut message = self.client.publish()
+            .topic_arn(&self.topic_arn)
+            .message(serde_json::to_string(envelope)?)
+            .message_attributes("eventType", sns_attribute(&envelope.event_type)?)
+            .message_attributes("storeId", sns_attribute(&envelope.store_id)?);
+        if self.topic_arn.ends_with(".fifo") {
+            message = message
+                .message_group_id(&envelope.store_id)
+                .message_deduplication_id(&envelope.event_id);
+        }
+        message.send()
+            .await
+            .map_err(|e| publish_error("SNS", aws_sdk_sns::error::DisplayErrorContext(&e)))?;
+        Ok(())
+    }
+}
+
+pub struct EventBridgePublisher {
+    client: aws_sdk_eventbridge::Client,
+    bus_name: String,
+    source: String,
+}
+
+#[async_trait]
+impl EventPublisher for EventBridgePublisher {
+    async fn publish(&self, envelope: &EventEnvelope) -> Result<(), GatewayError> {
+        let entry = PutEventsRequestEntry::builder()
+            .event_bus_name(&self.bus_name)
+            .source(&self.source)
+            .detail_type(&envelope.event_type)
+            .detail(serde_json::to_string(envelope)?)
+            .build();
+        let output = self.client.put_events()
+            .entries(entry)
+            .send()
+            .await
+            .map_err(|e| publish_error("EventBridge", aws_sdk_eventbridge::error::DisplayErrorContext(&e)))?;
+        if output.failed_entry_count() > 0 {
+            let reason = output.entries().first()
+                .and_then(|entry| entry.error_message())
+                .unwrap_or("unknown error");
+            return Err(GatewayError::Unexpected(format!("EventBridge rejected event: {}", reason)));
+        }
+        Ok(())
+    }
+}
+
+/// Development sink printing one envelope per line.
+pub struct StdoutPublisher;
+
+#[async_trait]
+impl EventPublisher for StdoutPublisher {
+    async fn publish(&self, envelope: &EventEnvelope) -> Result<(), GatewayError> {
+        println!("{}", serde_json::to_string(envelope)?);
+        Ok(())
+    }
+}
+
+/// Development sink appending one envelope per line to a file.
+pub struct FilePublisher {
+    path: String,
+}
+
+#[async_trait]
+impl EventPublisher for FilePublisher {
+    async fn publish(&self, envelope: &EventEnvelope) -> Result<(), GatewayError> {
+        let mut line = serde_json::to_string(envelope)?;
+        line.push('\n');
+        let mut file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .await
+            .map_err(|e| GatewayError::Unexpected(format!("Failed to open {}: {}", self.path, e)))?;
+        file.write_all(line.as_bytes())
+            .await
+            .map_err(|e| GatewayError::Unexpected(format!("Failed to write {}: {}", self.path, e)))
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum TargetConfig {
+    Sqs {
+        #[serde(rename = "queueUrl")]
+        queue_url: String,
+    },
+    Sns {
+        #[serde(rename = "topicArn")]
+        topic_arn: String,
+    },
+    EventBridge {
+        #[serde(rename = "busName")]
+        bus_name: String,
+        source: Option<String>,
+    },
+    Stdout,
+    File {
+        path: String,
+    },
+}
+
+#[derive(Deserialize)]
+struct RouteConfig {
+    #[serde(rename = "eventTypes", default)]
+    event_types: Vec<String>,
+    #[serde(default)]
+    stores: Vec<String>,
+    target: TargetConfig,
+}
+
+#[derive(Deserialize)]
+struct RoutesConfig {
+    routes: Vec<RouteConfig>,
+}
+
+struct Route {
+    event_types: Vec<String>,
+    stores: Vec<String>,
+    publisher: Arc<dyn EventPublisher>,
+}
+
+impl Route {
+    fn matches(&self, envelope: &EventEnvelope) -> bool {
+        let store_matches = self.stores.is_empty() || self.stores.contains(&envelope.store_id);
+        event_type_matches(&self.event_types, &envelope.event_type) && store_matches
+    }
+}
+
+/// Event type patterns are exact (`charge.succeeded`), prefixes (`charge.*`) or `*`.
+/// An empty list matches everything.
+pub fn event_type_matches(patterns: &[String], event_type: &str) -> bool {
+    patterns.is_empty() || patterns.iter().any(|pattern| match pattern.strip_suffix('*') {
+        Some(prefix) => event_type.starts_with(prefix),
+        None => pattern == event_type,
+    })
+}
+
+/// Fans verified webhook events out to the targets configured in the JSON file named by
+/// `WEBHOOK_ROUTES_CONFIG`. Without it no events are forwarded.
+pub struct EventRouter {
+    routes: Vec<Route>,
+}
+
+impl EventRouter {
+    pub async fn from_env() -> Result<Self, GatewayError> {
+        let path = match std::env::var("WEBHOOK_ROUTES_CONFIG") {
+            Ok(path) if !path.trim().is_empty() => path,
+            _ => return Ok(EventRouter { routes: Vec::new() }),
+        };
+        let contents = std::fs::read_to_string(&path)
+            .map_err(|e| GatewayError::Unexpected(format!("Failed to read routes config {}: {}", path, e)))?;
+        let config: RoutesConfig = serde_json::from_str(&contents)?;
+
+        let aws_config = aws_config::load_defaults(aws_config::BehaviorVersion::latest()).await;
+        let routes = config.routes.into_iter()
+            .map(|route| {
+                let publisher: Arc<dyn EventPublisher> = match route.target {
+                    TargetConfig::Sqs { queue_url } => Arc::new(SqsPublisher {
+                        client: aws_sdk_sqs::Client::new(&aws_config),
+                        queue_url,
+                    }),
+                    TargetConfig::Sns { topic_arn } => Arc::new(SnsPublisher {
+                        client: aws_sdk_sns::Client::new(&aws_config),
+                        topic_arn,
+                    }),
+                    TargetConfig::EventBridge { bus_name, source } => Arc::new(EventBridgePublisher {
+                        client: aws_sdk_eventbridge::Client::new(&aws_config),
+                        bus_name,
+                        source: source.unwrap_or_else(|| DEFAULT_EVENT_SOURCE.to_string()),
+                    }),
+                    TargetConfig::Stdout => Arc::new(StdoutPublisher),
+                    TargetConfig::File { path } => Arc::new(FilePublisher { path }),
+                };
+                Route {
+                    event_types: route.event_types,
+                    stores: route.stores,
+                    publisher,
+                }
+            })
+            .collect();
+        Ok(EventRouter { routes })
+    }
+
+    /// Publishes to every matching route and returns how many received the event. Any
+    /// failure is returned so the webhook is redelivered; targets that already succeeded
+    /// will then see the event again, so consumers must tolerate duplicates by `eventId`.
+    pub async fn dispatch(&self, envelope: &EventEnvelope) -> Result<usize, GatewayError> {
+        let mut published = 0;
+        for route in self.routes.iter().filter(|route| route.matches(envelope)) {
+            route.publisher.publish(envelope).await?;
+            published += 1;
+        }
+        Ok(published)
+    }
+}
+
+fn sqs_attribute(value: &str) -> Result<SqsAttribute, GatewayError> {
+    SqsAttribute::builder()
+        .data_type("String")
+        .string_value(value)
+        .build()
+        .map_err(|e| GatewayError::Unexpected(e.to_string()))
+}
+
+fn sns_attribute(value: &str) -> Result<SnsAttribute, GatewayError> {
+    SnsAttribute::builder()
+        .data_type("String")
+        .string_value(value)
+        .build()
+        .map_err(|e| GatewayError::Unexpected(e.to_string()))
+}
+
+fn publish_error(target: &str, error: impl std::fmt::Display) -> GatewayError {
+    GatewayError::Unexpected(format!("Failed to publish to {}: {}", target, error))
+}
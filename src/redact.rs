@@ -0,0 +1,197 @@
+use std::fmt;
+use std::sync::OnceLock;
+use regex::Regex;
+
+const MASK: &str = "****";
+
+/// Form fields whose values are safe to log verbatim. Everything else is masked.
+const LOGGABLE_FORM_FIELDS: &[&str] = &[
+    "amount", "currency", "mode", "type", "quantity", "unit_amount", "requested", "charge", "account",
+    "capture_method", "confirm", "amount_to_capture", "cancellation_reason", "billing_address_collection",
+    "allow_promotion_codes", "locale", "expires_at", "enabled", "optional",
+    "ui_mode", "off_session", "usage", "billing_scheme", "tiers_mode", "interval", "interval_count", "usage_type",
+    "up_to", "flat_amount", "active", "collection_method", "days_until_due", "auto_advance",
+    "pending_invoice_items_behavior", "percent_off", "amount_off", "duration", "duration_in_months", "max_redemptions",
+    "redeem_by",
+];
+
+/// A Stripe secret key. It has no `Display` implementation and its `Debug` output is masked,
+/// so it can only reach a log line or error message through an explicit `expose()`.
+#[derive(Clone)]
+pub struct ApiKey(String);
+
+impl ApiKey {
+    pub fn new(key: String) -> Self {
+        ApiKey(key)
+    }
+
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Debug for ApiKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ApiKey({})", mask(&self.0))
+    }
+}
+
+/// Keeps the last four characters of long values, e.g. `tok_visa_4242` becomes `****4242`.
+pub fn mask(value: &str) -> String {
+    let chars: Vec<char> = value.chars().collect();
+    if chars.len() <= 8 {
+        return MASK.to_string();
+    }
+    format!("{}{}", MASK, chars[chars.len() - 4..].iter().collect::<String>())
+}
+
+pub fn mask_opt(value: &Option<String>) -> Option<String> {
+    value.as_deref().map(mask)
+}
+
+/// Keeps the first character of the local part and the domain: `jane@example.com` becomes `j****@example.com`.
+pub fn mask_email(email: &str) -> String {
+    match email.split_once('@') {
+        Some((local, domain)) => {
+            let first = local.chars().next().map(String::from).unwrap_or_default();
+            format!("{}{}@{}", first, MASK, domain)
+        }
+        None => MASK.to_string(),
+    }
+}
+
+pub fn mask_email_opt(email: &Option<String>) -> Option<String> {
+    email.as_deref().map(mask_email)
+}
+
+/// Hides free text such as descriptions and names, which customers and merchants fill with
+/// whatever they like. Like `form`, an empty value stays visible.
+pub fn text(value: &str) -> String {
+    if value.is_empty() {
+        String::new()
+    } else {
+        MASK.to_string()
+    }
+}
+
+pub fn text_opt(value: &Option<String>) -> Option<String> {
+    value.as_deref().map(text)
+}
+
+/// Drops the query string and fragment, which is where session tokens and secrets live.
+pub fn url(value: &str) -> String {
+    match value.find(['?', '#']) {
+        Some(index) => format!("{}{}", &value[..index], MASK),
+        None => value.to_string(),
+    }
+}
+
+pub fn url_opt(value: &Option<String>) -> Option<String> {
+    value.as_deref().map(url)
+}
+
+/// Masks every value of an `application/x-www-form-urlencoded` body except known-safe fields.
+pub fn form(form_data: &str) -> String {
+    form_data.split('&')
+        .map(|pair| match pair.split_once('=') {
+            Some((key, value)) => {
+                let decoded = urlencoding::decode(key).map(|k| k.into_owned()).unwrap_or_else(|_| key.to_string());
+                let field = decoded.rsplit('[').next().unwrap_or(&decoded).trim_end_matches(']');
+                if LOGGABLE_FORM_FIELDS.contains(&field) {
+                    pair.to_string()
+                } else if value.is_empty() {
+                    format!("{}=", key)
+                } else {
+                    format!("{}={}", key, MASK)
+                }
+            }
+            None => pair.to_string(),
+        })
+        .collect::<Vec<String>>()
+        .join("&")
+}
+
+/// Removes anything that looks like a Stripe key or signing secret from free text,
+/// such as error messages echoed back from the Stripe API.
+pub fn secrets(text: &str) -> String {
+    static SECRET_PATTERN: OnceLock<Regex> = OnceLock::new();
+    let pattern = SECRET_PATTERN.get_or_init(|| {
+        Regex::new(r"\b(?:sk|rk|pk)_(?:live|test)_[A-Za-z0-9*]+|\bwhsec_[A-Za-z0-9]+").expect("valid secret pattern")
+    });
+    pattern.replace_all(text, MASK).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn masks_stripe_keys_and_signing_secrets_in_text() {
+        let text = "Invalid API Key provided: sk_live_51Habcdef0123456789, webhook secret whsec_abcDEF123";
+        let redacted = secrets(text);
+        assert!(!redacted.contains("sk_live_"));
+        assert!(!redacted.contains("whsec_"));
+        assert_eq!(redacted, "Invalid API Key provided: ****, webhook secret ****");
+    }
+
+    #[test]
+    fn masks_restricted_and_test_keys() {
+        assert_eq!(secrets("rk_live_abc123 and sk_test_abc123"), "**** and ****");
+    }
+
+    #[test]
+    fn debug_output_of_an_api_key_is_masked() {
+        let key = ApiKey::new("sk_live_51Habcdef0123456789".to_string());
+        assert_eq!(format!("{:?}", key), "ApiKey(****6789)");
+    }
+
+    #[test]
+    fn mask_keeps_only_the_last_four_characters() {
+        assert_eq!(mask("tok_visa_4242"), "****4242");
+        assert_eq!(mask("short"), "****");
+    }
+
+    #[test]
+    fn form_masks_everything_but_loggable_fields() {
+        let body = "amount=1000&currency=usd&source=tok_visa_4242&metadata%5Border%5D=A-1&description=";
+        assert_eq!(form(body), "amount=1000&currency=usd&source=****&metadata%5Border%5D=****&description=");
+    }
+
+    #[test]
+    fn url_drops_query_and_fragment() {
+        assert_eq!(url("https://checkout.stripe.com/c/pay/cs_test_a1#fidkdWxOYHwn"), "https://checkout.stripe.com/c/pay/cs_test_a1****");
+        assert_eq!(url("https://example.com/return?session=secret"), "https://example.com/return****");
+    }
+
+    #[test]
+    fn text_hides_anything_but_empty_values() {
+        assert_eq!(text("Order for Jane Doe, 12 High St"), "****");
+        assert_eq!(text(""), "");
+    }
+
+    #[test]
+    fn debug_output_of_a_request_hides_free_text_and_metadata_values() {
+        let request: crate::models::PaymentRequest = serde_json::from_value(serde_json::json!({
+            "storeId": "store-a",
+            "requestType": "CHARGE",
+            "amount": 1000,
+            "description": "Gift for Jane Doe",
+            "query": "email:'jane@example.com'",
+            "metadata": {"order": "A-1"},
+            "name": "Jane Doe",
+            "clientReferenceId": "customer-4411",
+        })).unwrap();
+        let debug = format!("{:?}", request);
+        for value in ["Gift for Jane Doe", "jane@example.com", "A-1", "Jane Doe", "customer-4411"] {
+            assert!(!debug.contains(value), "{} leaked into {}", value, debug);
+        }
+        assert!(debug.contains("amount: Some(1000)"));
+        assert!(debug.contains("\"order\""));
+    }
+
+    #[test]
+    fn mask_email_keeps_first_letter_and_domain() {
+        assert_eq!(mask_email("jane@example.com"), "j****@example.com");
+        assert_eq!(mask_email("not-an-email"), "****");
+    }
+}
@@ -1,42 +1,215 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
 use aws_sdk_secretsmanager::Client as SecretsManagerClient;
+use aws_sdk_secretsmanager::error::SdkError;
+use regex::Regex;
 use serde_json::Value;
 use crate::errors::GatewayError;
+use crate::merchant_webhooks::MerchantWebhookConfig;
+use crate::metrics;
+use crate::redact::ApiKey;
 
+const DEFAULT_STORE_ID_PATTERN: &str = "^[A-Za-z0-9_-]{1,64}$";
+const DEFAULT_STORE_SECRET_TEMPLATE: &str = "stripe/stores/{storeId}";
+const DEFAULT_SECRETS_CACHE_TTL_SECS: u64 = 300;
+
+/// Maps store IDs onto the Secrets Manager namespace the gateway is allowed to read.
+/// Configured with `STORE_ID_PATTERN`, which has to match the whole ID, and
+/// `STORE_SECRET_TEMPLATE` (default `stripe/stores/{storeId}`), which has to start with a
+/// fixed prefix so a store ID can never name a secret outside it.
+pub struct StoreNamespace {
+    pattern: Regex,
+    template: String,
+}
+
+impl StoreNamespace {
+    pub fn from_env() -> Result<Self, GatewayError> {
+        let pattern = std::env::var("STORE_ID_PATTERN")
+            .unwrap_or_else(|_| DEFAULT_STORE_ID_PATTERN.to_string());
+        let template = std::env::var("STORE_SECRET_TEMPLATE")
+            .unwrap_or_else(|_| DEFAULT_STORE_SECRET_TEMPLATE.to_string());
+        Self::new(&pattern, template)
+    }
+
+    pub fn new(pattern: &str, template: String) -> Result<Self, GatewayError> {
+        if !pattern.starts_with('^') || !pattern.ends_with('$') {
+            return Err(GatewayError::Unexpected("Store ID pattern must be anchored with ^ and $".to_string()));
+        }
+        let pattern = Regex::new(pattern)
+            .map_err(|e| GatewayError::Unexpected(format!("Invalid store ID pattern: {}", e)))?;
+        if !template.contains("{storeId}") {
+            return Err(GatewayError::Unexpected("Store secret template must contain {storeId}".to_string()));
+        }
+        if template.starts_with("{storeId}") {
+            return Err(GatewayError::Unexpected("Store secret template must start with a prefix such as stripe/stores/".to_string()));
+        }
+        Ok(StoreNamespace { pattern, template })
+    }
+
+    pub fn secret_id(&self, store_id: &str) -> Result<String, GatewayError> {
+        if store_id.is_empty() {
+            return Err(GatewayError::InvalidRequest("Store ID cannot be empty".to_string()));
+        }
+        if !self.pattern.is_match(store_id) {
+            return Err(GatewayError::InvalidRequest(format!("Invalid store ID: {}", store_id)));
+        }
+        Ok(self.template.replace("{storeId}", store_id))
+    }
+}
+
+/// Everything the gateway keeps in a store's secret.
+#[derive(Clone)]
+pub struct StoreSecret {
+    pub api_key: ApiKey,
+    /// The `merchantWebhooks` field, when the store subscribes to outbound webhooks.
+    pub merchant_webhooks: Option<MerchantWebhookConfig>,
+    /// The `billingPortalConfiguration` field: the Customer Portal configuration (`bpc_...`)
+    /// used for the store's portal sessions instead of the account default.
+    pub billing_portal_configuration: Option<String>,
+}
+
+/// Resolves store secrets from Secrets Manager, caching them for `SECRETS_CACHE_TTL_SECS`
+/// (default 300) so warm invocations skip the lookup.
 pub struct SecretsService {
     client: SecretsManagerClient,
+    namespace: StoreNamespace,
+    cache: Mutex<HashMap<String, (StoreSecret, Instant)>>,
+    cache_ttl: Duration,
 }
 
 impl SecretsService {
-    pub async fn new() -> Result<Self, GatewayError> {
-        let config = aws_config::load_from_env().await;
+    pub async fn new(namespace: StoreNamespace) -> Result<Self, GatewayError> {
+        let config = aws_config::load_defaults(aws_config::BehaviorVersion::latest()).await;
         let client = SecretsManagerClient::new(&config);
-        Ok(SecretsService { client })
+        let cache_ttl = std::env::var("SECRETS_CACHE_TTL_SECS")
+            .ok()
+            .and_then(|ttl| ttl.parse().ok())
+            .unwrap_or(DEFAULT_SECRETS_CACHE_TTL_SECS);
+        Ok(SecretsService {
+            client,
+            namespace,
+            cache: Mutex::new(HashMap::new()),
+            cache_ttl: Duration::from_secs(cache_ttl),
+        })
     }
 
-    pub async fn get_secret(&self, secret_id: &str) -> Result<String, GatewayError> {
-        if secret_id.is_empty() {
-            return Err(GatewayError::InvalidRequest("Store ID cannot be empty".to_string()));
+    #[tracing::instrument(name = "get_secret", skip(self))]
+    pub async fn get_secret(&self, store_id: &str) -> Result<StoreSecret, GatewayError> {
+        let secret_id = self.namespace.secret_id(store_id)?;
+        if let Some(secret) = self.cached(&secret_id) {
+            metrics::count("SecretsCacheHit", &[]);
+            return Ok(secret);
+        }
+        metrics::count("SecretsCacheMiss", &[]);
+
+        let secret = self.fetch_secret(store_id, &secret_id).await?;
+        if let Ok(mut cache) = self.cache.lock() {
+            cache.insert(secret_id, (secret.clone(), Instant::now()));
         }
-        let response = self.client.get_secret_value()
+        Ok(secret)
+    }
+
+    fn cached(&self, secret_id: &str) -> Option<StoreSecret> {
+        let cache = self.cache.lock().ok()?;
+        cache.get(secret_id)
+            .filter(|(_, fetched_at)| fetched_at.elapsed() < self.cache_ttl)
+            .map(|(secret, _)| secret.clone())
+    }
+
+    async fn fetch_secret(&self, store_id: &str, secret_id: &str) -> Result<StoreSecret, GatewayError> {
+        let response = match self.client.get_secret_value()
             .secret_id(secret_id)
             .send()
-            .await?;
+            .await
+        {
+            Ok(response) => response,
+            Err(SdkError::ServiceError(e)) if e.err().is_resource_not_found_exception() => {
+                return Err(GatewayError::UnknownStore(store_id.to_string()));
+            }
+            Err(e) => return Err(e.into()),
+        };
 
         let secret_string = response.secret_string
-            .ok_or_else(|| GatewayError::Unexpected(format!("Secret is empty for store: {}", secret_id)))?;
+            .ok_or_else(|| GatewayError::Unexpected(format!("Secret is empty for store: {}", store_id)))?;
 
         // Try parsing as JSON to extract stripeSecretKey
         if let Ok(json) = serde_json::from_str::<Value>(&secret_string) {
             if let Some(api_key) = json.get("stripeSecretKey").and_then(|v| v.as_str()) {
                 if !api_key.trim().is_empty() {
-                    return Ok(api_key.to_string());
+                    let merchant_webhooks = json.get("merchantWebhooks")
+                        .map(|config| serde_json::from_value(config.clone()))
+                        .transpose()
+                        .map_err(|e| GatewayError::Unexpected(format!("Invalid merchantWebhooks for store {}: {}", store_id, e)))?;
+                    let billing_portal_configuration = json.get("billingPortalConfiguration")
+                        .and_then(|v| v.as_str())
+                        .filter(|configuration| !configuration.trim().is_empty())
+                        .map(String::from);
+                    return Ok(StoreSecret {
+                        api_key: ApiKey::new(api_key.to_string()),
+                        merchant_webhooks,
+                        billing_portal_configuration,
+                    });
                 }
             }
         }
 
         if secret_string.trim().is_empty() {
-            return Err(GatewayError::Unexpected(format!("Secret is empty for store: {}", secret_id)));
+            return Err(GatewayError::Unexpected(format!("Secret is empty for store: {}", store_id)));
+        }
+        Ok(StoreSecret {
+            api_key: ApiKey::new(secret_string),
+            merchant_webhooks: None,
+            billing_portal_configuration: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn default_namespace() -> StoreNamespace {
+        StoreNamespace::new(DEFAULT_STORE_ID_PATTERN, DEFAULT_STORE_SECRET_TEMPLATE.to_string()).unwrap()
+    }
+
+    #[test]
+    fn default_namespace_maps_store_ids_under_the_prefix() {
+        let namespace = default_namespace();
+        assert_eq!(namespace.secret_id("store-a_1").unwrap(), "stripe/stores/store-a_1");
+        assert!(namespace.secret_id(&"a".repeat(64)).is_ok());
+        assert!(matches!(namespace.secret_id(&"a".repeat(65)), Err(GatewayError::InvalidRequest(_))));
+        assert!(matches!(namespace.secret_id(""), Err(GatewayError::InvalidRequest(_))));
+    }
+
+    #[test]
+    fn default_namespace_rejects_ids_that_leave_the_prefix() {
+        let namespace = default_namespace();
+        for store_id in ["../admin", "store-a/../../prod/db", "store-a/other", "prod%2Fdb", "store a", "store-a\n"] {
+            assert!(
+                matches!(namespace.secret_id(store_id), Err(GatewayError::InvalidRequest(_))),
+                "{:?} was accepted", store_id
+            );
         }
-        Ok(secret_string)
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn template_needs_a_prefix_and_the_store_id() {
+        assert!(StoreNamespace::new(DEFAULT_STORE_ID_PATTERN, "stripe/stores".to_string()).is_err());
+        assert!(StoreNamespace::new(DEFAULT_STORE_ID_PATTERN, "{storeId}".to_string()).is_err());
+        assert!(StoreNamespace::new(DEFAULT_STORE_ID_PATTERN, "{storeId}/stripe".to_string()).is_err());
+        assert!(StoreNamespace::new(DEFAULT_STORE_ID_PATTERN, "prod/{storeId}/stripe".to_string()).is_ok());
+    }
+
+    #[test]
+    fn pattern_has_to_be_anchored() {
+        let template = DEFAULT_STORE_SECRET_TEMPLATE.to_string();
+        assert!(StoreNamespace::new("[a-z]+", template.clone()).is_err());
+        assert!(StoreNamespace::new("^[a-z]+", template.clone()).is_err());
+        assert!(StoreNamespace::new("[a-z]+$", template.clone()).is_err());
+        assert!(StoreNamespace::new("^[a-z+$", template.clone()).is_err());
+        let namespace = StoreNamespace::new("^[a-z]+$", template).unwrap();
+        assert!(namespace.secret_id("store").is_ok());
+        assert!(namespace.secret_id("store/../x").is_err());
+    }
+}
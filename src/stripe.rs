@@ -0,0 +1,248 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use reqwest::{Client as HttpClient, RequestBuilder, Response, StatusCode};
+use serde_json::Value;
+use tracing::{field, Instrument};
+use urlencoding::encode;
+use uuid::Uuid;
+use crate::errors::GatewayError;
+use crate::metrics::{self, Unit};
+use crate::redact::{self, ApiKey};
+
+const STRIPE_API_BASE: &str = "https://api.stripe.com/v1";
+const STRIPE_FILES_BASE: &str = "https://files.stripe.com/v1";
+const MAX_RETRIES: u32 = 2;
+const RETRY_BASE_DELAY_MS: u64 = 250;
+
+pub struct StripeClient {
+    http_client: HttpClient,
+    api_key: ApiKey,
+    idempotency_key: Option<String>,
+    stripe_account: Option<String>,
+    posts_sent: AtomicUsize,
+}
+
+impl StripeClient {
+    pub fn new(api_key: ApiKey) -> Self {
+        StripeClient {
+            http_client: HttpClient::new(),
+            api_key,
+            idempotency_key: None,
+            stripe_account: None,
+            posts_sent: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn with_idempotency_key(mut self, idempotency_key: Option<String>) -> Self {
+        self.idempotency_key = idempotency_key.filter(|key| !key.is_empty());
+        self
+    }
+
+    /// Makes every call on behalf of a connected account, through the `Stripe-Account` header.
+    pub fn with_stripe_account(mut self, account_id: Option<String>) -> Self {
+        self.stripe_account = account_id.filter(|id| !id.is_empty());
+        self
+    }
+
+    pub async fn get(&self, path: &str) -> Result<Value, GatewayError> {
+        self.get_with_params::<&str, &str>(path, &[]).await
+    }
+
+    pub async fn get_with_params<K, V>(&self, path: &str, params: &[(K, V)]) -> Result<Value, GatewayError>
+    where
+        K: AsRef<str> + Sync,
+        V: AsRef<str> + Sync,
+    {
+        let mut url = format!("{}{}", STRIPE_API_BASE, path);
+        if !params.is_empty() {
+            url.push('?');
+            url.push_str(&encode_form(params));
+        }
+
+        let request = self.http_client.get(&url);
+        self.send("GET", path, request, None).await
+    }
+
+    pub async fn post<K, V>(&self, path: &str, params: &[(K, V)]) -> Result<Value, GatewayError>
+    where
+        K: AsRef<str> + Sync,
+        V: AsRef<str> + Sync,
+    {
+        let form_data = encode_form(params);
+
+        tracing::debug!("Form data: {}", redact::form(&form_data));
+
+        let request = self.http_client.post(format!("{}{}", STRIPE_API_BASE, path))
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .body(form_data);
+        let idempotency_key = self.next_idempotency_key();
+        self.send("POST", path, request, idempotency_key).await
+    }
+
+    pub async fn delete(&self, path: &str) -> Result<Value, GatewayError> {
+        let request = self.http_client.delete(format!("{}{}", STRIPE_API_BASE, path));
+        self.send("DELETE", path, request, None).await
+    }
+
+    /// Uploads a file to the Files API, e.g. with purpose `dispute_evidence`. The multipart
+    /// body is built in memory so the request can be retried like any other POST.
+    pub async fn upload_file(
+        &self,
+        purpose: &str,
+        file_name: &str,
+        content_type: &str,
+        content: &[u8],
+    ) -> Result<Value, GatewayError> {
+        let boundary = format!("gateway-{}", Uuid::new_v4().simple());
+        let mut body = Vec::with_capacity(content.len() + 512);
+        body.extend_from_slice(format!(
+            "--{boundary}\r\nContent-Disposition: form-data; name=\"purpose\"\r\n\r\n{purpose}\r\n",
+        ).as_bytes());
+        body.extend_from_slice(format!(
+            "--{boundary}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"{}\"\r\nContent-Type: {}\r\n\r\n",
+            file_name.replace(['"', '\r', '\n'], "_"),
+            content_type.replace(['\r', '\n'], ""),
+        ).as_bytes());
+        body.extend_from_slice(content);
+        body.extend_from_slice(format!("\r\n--{boundary}--\r\n").as_bytes());
+
+        let request = self.http_client.post(format!("{}/files", STRIPE_FILES_BASE))
+            .header("Content-Type", format!("multipart/form-data; boundary={}", boundary))
+            .body(body);
+        let idempotency_key = self.next_idempotency_key();
+        self.send("POST", "/files", request, idempotency_key).await
+    }
+
+    /// A request may issue several POSTs; each gets its own key derived from the caller's
+    /// so that a retried request replays every one of them instead of tripping Stripe's
+    /// "same key, different parameters" check.
+    fn next_idempotency_key(&self) -> Option<String> {
+        let key = self.idempotency_key.as_ref()?;
+        match self.posts_sent.fetch_add(1, Ordering::SeqCst) {
+            0 => Some(key.clone()),
+            n => Some(format!("{}-{}", key, n)),
+        }
+    }
+
+    /// Sends the request, retrying connection failures, `429`s and `5xx`s when that is safe:
+    /// always for GETs and DELETEs, and for POSTs only when they carry an idempotency key.
+    async fn send(
+        &self,
+        method: &str,
+        path: &str,
+        request: RequestBuilder,
+        idempotency_key: Option<String>,
+    ) -> Result<Value, GatewayError> {
+        let endpoint = format!("{} {}", method, endpoint_name(path));
+        let span = tracing::info_span!(
+            "stripe_request",
+            otel.kind = "client",
+            http_method = method,
+            stripe_path = path,
+            idempotency_key = field::Empty,
+            stripe_account = field::Empty,
+            stripe_request_id = field::Empty,
+        );
+        let mut request = request.header("Authorization", format!("Bearer {}", self.api_key.expose()));
+        if let Some(account) = &self.stripe_account {
+            span.record("stripe_account", account.as_str());
+            request = request.header("Stripe-Account", account);
+        }
+        if let Some(key) = &idempotency_key {
+            span.record("idempotency_key", key.as_str());
+            request = request.header("Idempotency-Key", key);
+        }
+        let retryable = method == "GET" || method == "DELETE" || idempotency_key.is_some();
+
+        async move {
+            let mut attempt = 0;
+            loop {
+                let attempt_request = request.try_clone()
+                    .ok_or_else(|| GatewayError::Unexpected("Stripe request cannot be cloned".to_string()))?;
+                let started = Instant::now();
+                let result = attempt_request.send().await;
+                let elapsed = started.elapsed().as_millis() as f64;
+
+                let should_retry = match &result {
+                    Ok(response) => {
+                        let status = response.status();
+                        let status_code = status.as_u16().to_string();
+                        metrics::put("StripeLatency", elapsed, Unit::Milliseconds, &[("StripeEndpoint", &endpoint)]);
+                        metrics::count("StripeResponses", &[("StripeEndpoint", &endpoint), ("StatusCode", &status_code)]);
+                        status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+                    }
+                    Err(e) => {
+                        metrics::count("StripeConnectionErrors", &[("StripeEndpoint", &endpoint)]);
+                        e.is_connect() || e.is_timeout()
+                    }
+                };
+
+                if should_retry && retryable && attempt < MAX_RETRIES {
+                    attempt += 1;
+                    metrics::count("StripeRetries", &[("StripeEndpoint", &endpoint)]);
+                    tracing::warn!(attempt, "Retrying Stripe API call");
+                    tokio::time::sleep(Duration::from_millis(RETRY_BASE_DELAY_MS << (attempt - 1))).await;
+                    continue;
+                }
+
+                let response = result.map_err(|e| GatewayError::StripeError(e.without_url()))?;
+                if let Some(request_id) = response.headers().get("request-id").and_then(|v| v.to_str().ok()) {
+                    tracing::Span::current().record("stripe_request_id", request_id);
+                }
+                tracing::info!(http_status = response.status().as_u16(), "Stripe API call completed");
+
+                return handle_stripe_response(response).await;
+            }
+        }
+        .instrument(span)
+        .await
+    }
+}
+
+/// Collapses object IDs out of a request path so it can be used as a metric dimension,
+/// e.g. `/charges/ch_3Nq.../capture` becomes `/charges/{id}/capture`. Stripe IDs always
+/// contain digits or capitals, resource names never do.
+fn endpoint_name(path: &str) -> String {
+    path.split('/')
+        .map(|segment| {
+            if segment.chars().any(|c| c.is_ascii_digit() || c.is_ascii_uppercase()) {
+                "{id}"
+            } else {
+                segment
+            }
+        })
+        .collect::<Vec<&str>>()
+        .join("/")
+}
+
+pub fn encode_form<K: AsRef<str>, V: AsRef<str>>(params: &[(K, V)]) -> String {
+    params.iter()
+        .map(|(k, v)| format!("{}={}", encode(k.as_ref()), encode(v.as_ref())))
+        .collect::<Vec<String>>()
+        .join("&")
+}
+
+async fn handle_stripe_response(response: Response) -> Result<Value, GatewayError> {
+    let status = response.status();
+    let body = response.json::<Value>().await
+        .map_err(|e| GatewayError::StripeError(e.without_url()))?;
+    if status.is_success() {
+        Ok(body)
+    } else {
+        let message = body["error"]["message"].as_str()
+            .unwrap_or("Unknown Stripe error")
+            .to_string();
+        if let Some(decline_code) = body["error"]["decline_code"].as_str() {
+            metrics::count("Declines", &[("DeclineCode", decline_code)]);
+        }
+        tracing::warn!("Stripe API error: {}", redact::secrets(&message));
+        let error = &body["error"];
+        if error["code"] == "authentication_required" || error["decline_code"] == "authentication_required" {
+            return Err(GatewayError::AuthenticationRequired {
+                message: redact::secrets(&message),
+                payment_intent_id: error["payment_intent"]["id"].as_str().map(String::from),
+            });
+        }
+        Err(GatewayError::InvalidRequest(redact::secrets(&message)))
+    }
+}
@@ -0,0 +1,82 @@
+use std::collections::HashMap;
+use opentelemetry::propagation::{Extractor, TextMapCompositePropagator, TextMapPropagator};
+use opentelemetry::Context;
+use opentelemetry_aws::trace::XrayPropagator;
+use opentelemetry_otlp::SpanExporter;
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::trace::SdkTracerProvider;
+use opentelemetry_sdk::Resource;
+use serde_json::Value;
+use crate::errors::GatewayError;
+
+const DEFAULT_SERVICE_NAME: &str = "stripe-gateway";
+const XRAY_HEADER: &str = "x-amzn-trace-id";
+
+/// Builds an OTLP/HTTP tracer provider when `OTEL_EXPORTER_OTLP_ENDPOINT` or
+/// `OTEL_EXPORTER_OTLP_TRACES_ENDPOINT` is set (e.g. `http://localhost:4318` for a local
+/// collector). Returns `None` when tracing export is not configured.
+pub fn tracer_provider() -> Result<Option<SdkTracerProvider>, GatewayError> {
+    let configured = ["OTEL_EXPORTER_OTLP_ENDPOINT", "OTEL_EXPORTER_OTLP_TRACES_ENDPOINT"]
+        .iter()
+        .any(|name| std::env::var(name).map(|v| !v.trim().is_empty()).unwrap_or(false));
+    if !configured {
+        return Ok(None);
+    }
+
+    let exporter = SpanExporter::builder()
+        .with_http()
+        .build()
+        .map_err(|e| GatewayError::Unexpected(format!("Failed to build OTLP exporter: {}", e)))?;
+    let service_name = std::env::var("OTEL_SERVICE_NAME").unwrap_or_else(|_| DEFAULT_SERVICE_NAME.to_string());
+
+    Ok(Some(SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_resource(Resource::builder().with_service_name(service_name).build())
+        .build()))
+}
+
+/// Extracts the caller's trace context from the event headers, accepting either W3C
+/// `traceparent` or an X-Ray `X-Amzn-Trace-Id`. Falls back to the X-Ray trace ID Lambda
+/// assigned to the invocation.
+pub fn parent_context(event: &Value, xray_trace_id: Option<&str>) -> Context {
+    let mut headers: HashMap<String, String> = event.get("headers")
+        .and_then(|headers| headers.as_object())
+        .map(|headers| {
+            headers.iter()
+                .filter_map(|(key, value)| value.as_str().map(|v| (key.to_lowercase(), v.to_string())))
+                .collect()
+        })
+        .unwrap_or_default();
+    if !headers.contains_key("traceparent") && !headers.contains_key(XRAY_HEADER) {
+        if let Some(trace_id) = xray_trace_id {
+            headers.insert(XRAY_HEADER.to_string(), trace_id.to_string());
+        }
+    }
+
+    let propagator = TextMapCompositePropagator::new(vec![
+        Box::new(TraceContextPropagator::new()),
+        Box::new(XrayPropagator::new()),
+    ]);
+    propagator.extract(&HeaderExtractor(&headers))
+}
+
+/// Lambda freezes the container between invocations, so spans are exported before returning.
+pub async fn flush(provider: &SdkTracerProvider) {
+    let provider = provider.clone();
+    let result = tokio::task::spawn_blocking(move || provider.force_flush()).await;
+    if let Ok(Err(e)) = result {
+        tracing::warn!("Failed to flush traces: {}", e);
+    }
+}
+
+struct HeaderExtractor<'a>(&'a HashMap<String, String>);
+
+impl Extractor for HeaderExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(&key.to_lowercase()).map(String::as_str)
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(String::as_str).collect()
+    }
+}